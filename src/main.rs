@@ -1,282 +1,6776 @@
 use colored::Colorize;
-use regex::{Captures, Regex, RegexBuilder};
+use encoding_rs::Encoding;
+use ignore::{DirEntry, WalkBuilder, WalkState};
+use notify::{EventKind, RecursiveMode, Watcher};
+use regex::{Captures, Match, Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::env;
 use std::fs::{self, File};
-use std::io::{BufRead, BufReader};
-use walkdir::WalkDir;
+use std::io::{self, BufRead, BufReader, Cursor, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::LazyLock;
+use std::thread;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use unicode_normalization::UnicodeNormalization;
+
+// completion scripts for --generate-completions, the upper case names are filled in from OPTIONS
+const BASH_COMPLETION: &str = r#"FUNCTION() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    local prev="${COMP_WORDS[COMP_CWORD-1]}"
+    case "$prev" in
+        PATH_OPTIONS)
+            COMPREPLY=($(compgen -f -- "$cur"))
+            return
+            ;;
+        VALUE_OPTIONS)
+            return
+            ;;
+    esac
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=($(compgen -W "FLAGS" -- "$cur"))
+    else
+        COMPREPLY=($(compgen -f -- "$cur"))
+    fi
+}
+complete -F FUNCTION PROGRAM"#;
+
+const ZSH_COMPLETION: &str = r#"#compdef PROGRAM
+
+FUNCTION() {
+    case "${words[CURRENT-1]}" in
+        PATH_OPTIONS)
+            _files
+            return
+            ;;
+        VALUE_OPTIONS)
+            return
+            ;;
+    esac
+    if [[ "$PREFIX" == -* ]]; then
+        compadd -- FLAGS
+    else
+        _files
+    fi
+}
+
+FUNCTION "$@""#;
+
+// set for the copy of the program started by --pager, its output goes to the pager instead of straight to the terminal
+const PAGED_ENV: &str = "SEARCH_UTILITY_PAGED";
+
+// how long --watch waits for files to stop changing before searching again, an editor saving a file sends several events
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(200);
+
+// set by --errors-to-stdout for CI systems that only capture stdout, errors go to stderr otherwise
+static ERRORS_TO_STDOUT: AtomicBool = AtomicBool::new(false);
+
+// the timestamp forms --time-group buckets by, a date optionally followed by a time like 2024-05-01 13:45 or 2024-05-01T13:45:10
+static TIMESTAMP: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(\d{4})-(\d{2})-(\d{2})(?:[T ](\d{2}):(\d{2}))?")
+        .expect("timestamp regex is valid")
+});
+
+// the colors --color-groups gives capture groups 1, 2, 3 and so on, starting over after the last one
+const GROUP_COLORS: &[colored::Color] = &[
+    colored::Color::Green,
+    colored::Color::Blue,
+    colored::Color::Magenta,
+    colored::Color::Cyan,
+    colored::Color::Yellow,
+];
+
+// the color and hyperlink escape sequences written to the terminal, they take up no space when measuring for --wrap
+static ANSI_ESCAPE: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"\x1b\[[0-9;]*m|\x1b\]8;;[^\x1b]*\x1b\\").expect("escape sequence regex is valid")
+});
 
 //The Config Struct holds the data assocaited with the Command Line Argument
 struct Config {
     pattern: String,
+    patterns: Vec<String>,
+    pattern_stats: Vec<Regex>,
     file_paths: Vec<String>,
+    clipboard: Option<String>,
     case_insensitive: bool,
+    ascii_case: bool,
+    no_unicode: bool,
+    regex_mode: bool,
+    literal_prefix: Option<String>,
+    word_regexp: bool,
+    word_chars: Option<Regex>,
     print_line_numbers: bool,
     invert_match: bool,
     recursive_search: bool,
+    exclude_dirs: Vec<glob::Pattern>,
+    git_diff_lines: Option<HashMap<PathBuf, BTreeSet<usize>>>,
+    index: Option<String>,
     print_filenames: bool,
+    no_filename: bool,
+    path_display: Option<PathDisplay>,
     colored_output: bool,
+    highlight_line: bool,
+    color_groups: bool,
+    show_whitespace: bool,
+    hyperlink: bool,
+    max_total: Option<usize>,
+    head: Option<usize>,
+    tail: Option<usize>,
+    max_count: Option<usize>,
+    before_context: usize,
+    after_context: usize,
+    companion_offset: Option<isize>,
+    only_matching: bool,
+    json_escape: bool,
+    normalize: Option<NormalForm>,
+    count: bool,
+    rank: bool,
+    list_sorted: bool,
+    with_zero: bool,
+    min_count: Option<usize>,
+    count_total: bool,
+    count_format: CountFormat,
+    group_by: Option<usize>,
+    time_group: Option<TimeUnit>,
+    time_regex: Option<Regex>,
+    unique_lines: bool,
+    max_per_line: Option<usize>,
+    max_line_length: Option<usize>,
+    overlapping: bool,
+    replace: Option<String>,
+    replace_case: Vec<CaseSegment>,
+    replace_tokens: bool,
+    if_match: Option<Regex>,
+    replace_max_per_line: Option<usize>,
+    keep_indent: bool,
+    delete_matches: bool,
+    subs: Vec<Substitution>,
+    in_place: bool,
+    confirm: bool,
+    confirm_summary: bool,
+    max_changes: Option<usize>,
+    transaction: bool,
+    patch_out: Option<String>,
+    check: bool,
+    preview: Option<usize>,
+    strict_utf8: bool,
+    keep_newline: bool,
+    multiline: bool,
+    column: bool,
+    encoding_fallback: Option<&'static Encoding>,
+    verbose: bool,
+    no_messages: bool,
+    print0_lines: bool,
+    silent_scan: bool,
+    strict: bool,
+    summary_only: bool,
+    sort_output: Option<SortOrder>,
+    csv: bool,
+    json_pretty: bool,
+    json: bool,
+    bytes: bool,
+    search_compressed: bool,
+    stats: bool,
+    timings: bool,
+    pager: bool,
+    watch_roots: Option<Vec<String>>,
+    max_memory: Option<usize>,
+    regex_size_limit: Option<usize>,
+    wrap_width: Option<usize>,
+    field: Option<usize>,
+    compare: bool,
+    delimiter: char,
     help: bool,
+    completions: Option<String>,
+    subcommand: Subcommand,
+}
+
+//The CountFormat Enum holds how --count-format prints the number of matches in each file
+#[derive(Clone, Copy, PartialEq)]
+enum CountFormat {
+    Plain,
+    KeyValue,
+    Json,
+}
+
+//The NormalForm Enum holds the Unicode normalization form --normalize puts matched text into
+#[derive(Clone, Copy)]
+enum NormalForm {
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd,
+}
+
+//The TimeUnit Enum holds how long each --time-group bucket of matches is
+#[derive(Clone, Copy)]
+enum TimeUnit {
+    Minute,
+    Hour,
+    Day,
+}
+
+//The PathDisplay Enum holds how --path-display shows the paths of matched files
+#[derive(Clone, Copy)]
+enum PathDisplay {
+    Relative,
+    Absolute,
+}
+
+//The Subcommand Enum holds the mode picked by the first argument, search is used when no subcommand is given
+#[derive(Clone, Copy, PartialEq)]
+enum Subcommand {
+    Search,
+    Replace,
+}
+
+// options that have no meaning when replacing, the replace subcommand rejects them
+const SEARCH_ONLY_OPTIONS: &[&str] = &[
+    "--replace",
+    "--sub",
+    "--delete-matches",
+    "--count",
+    "--rank",
+    "--list-sorted",
+    "--count-files",
+    "--with-zero",
+    "--min-count",
+    "--count-total",
+    "--count-format",
+    "--group-by",
+    "--time-group",
+    "--time-regex",
+    "--unique-lines",
+    "--csv",
+    "--field",
+    "--delimiter",
+    "--json-pretty",
+    "--json",
+    "-o",
+    "--max-per-line",
+    "--overlapping",
+    "--bytes",
+    "-A",
+    "-B",
+    "-C",
+    "--companion-offset",
+    "--per-pattern-stats",
+    "--summary-only",
+];
+
+// every option the parser knows with the name of its value, or "" for flags that take none,
+// long options missing from here are rejected and the completion scripts are generated from it
+const OPTIONS: &[(&str, &str)] = &[
+    ("-i", ""),
+    ("--ascii-case", ""),
+    ("--no-unicode", ""),
+    ("-E", ""),
+    ("--literal-prefix", "<text>"),
+    ("-e", "<pattern>"),
+    ("--per-pattern-stats", ""),
+    ("-w", ""),
+    ("--word-chars", "<set>"),
+    ("-n", ""),
+    ("-v", ""),
+    ("-r", ""),
+    ("--git-root", ""),
+    ("--clipboard", ""),
+    ("--git-diff", ""),
+    ("--exclude-dir", "<glob>"),
+    ("-f", ""),
+    ("--no-filename", ""),
+    ("--path-display=relative", ""),
+    ("--path-display=absolute", ""),
+    ("-c", ""),
+    ("--color=always", ""),
+    ("--color=never", ""),
+    ("--color=auto", ""),
+    ("--highlight-line", ""),
+    ("--color-groups", ""),
+    ("--show-whitespace", ""),
+    ("--highlight-whitespace", ""),
+    ("--hyperlink", ""),
+    ("-h", ""),
+    ("--help", ""),
+    ("--max-matches-total", "<n>"),
+    ("--head", "<n>"),
+    ("--tail", "<n>"),
+    ("-m", "<n>"),
+    ("-A", "<n>"),
+    ("-B", "<n>"),
+    ("-C", "<n>"),
+    ("--companion-offset", "<n>"),
+    ("-o", ""),
+    ("--json-escape", ""),
+    ("--normalize", "<form>"),
+    ("--count", ""),
+    ("--rank", ""),
+    ("--list-sorted", ""),
+    ("--unique-lines", ""),
+    ("--count-files", ""),
+    ("--with-zero", ""),
+    ("--min-count", "<n>"),
+    ("--count-total", ""),
+    ("--count-format", "<style>"),
+    ("--group-by", "<n>"),
+    ("--time-group", "<unit>"),
+    ("--time-regex", "<pattern>"),
+    ("--overlapping", ""),
+    ("--max-per-line", "<n>"),
+    ("--max-line-length", "<n>"),
+    ("--replace", "<text>"),
+    ("--if-match", "<pattern>"),
+    ("--prepend", "<text>"),
+    ("--append", "<text>"),
+    ("--replace-max-per-line", "<n>"),
+    ("--keep-indent", ""),
+    ("--delete-matches", ""),
+    ("--sub", "<find>=<replace>"),
+    ("--in-place", ""),
+    ("--confirm", ""),
+    ("--confirm-summary", ""),
+    ("--transaction", ""),
+    ("--check", ""),
+    ("--patch-out", "<path>"),
+    ("--max-changes", "<n>"),
+    ("--preview", "<n>"),
+    ("--strict-utf8", ""),
+    ("-U", ""),
+    ("--multiline", ""),
+    ("--column", ""),
+    ("--keep-newline", ""),
+    ("--encoding-fallback", "<name>"),
+    ("--verbose", ""),
+    ("-S", ""),
+    ("--no-messages", ""),
+    ("--print0-lines", ""),
+    ("--silent-scan", ""),
+    ("--strict", ""),
+    ("--sort-output", ""),
+    ("--sort-output=reverse", ""),
+    ("--csv", ""),
+    ("--field", "<n>"),
+    ("--delimiter", "<char>"),
+    ("--json-pretty", ""),
+    ("--json", ""),
+    ("--bytes", ""),
+    ("-z", ""),
+    ("--search-zip", ""),
+    ("--search-compressed", ""),
+    ("--stats", ""),
+    ("--summary-only", ""),
+    ("--timings", ""),
+    ("--pager", ""),
+    ("--watch", ""),
+    ("--wrap", ""),
+    ("--compare", ""),
+    ("--max-memory", "<size>"),
+    ("--regex-size-limit", "<size>"),
+    ("--index", "<path>"),
+    ("--files-from", "<path>"),
+    ("--generate-completions", "<shell>"),
+    ("--errors-to-stdout", ""),
+];
+
+//The SortOrder Enum holds the order output lines are printed in when --sort-output is used
+#[derive(Clone, Copy)]
+enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+//The RunTotals Struct holds the counts accumulated across every file searched in a run
+struct RunTotals {
+    matches: usize,
+    replacements: usize,
+    sub_counts: Vec<usize>,
+    pattern_lines: Vec<usize>,
+    pattern_files: Vec<usize>,
+    group_counts: HashMap<String, usize>,
+    time_buckets: BTreeMap<String, usize>,
+    lines_scanned: usize,
+    bytes_scanned: usize,
+    files_searched: usize,
+    files_matched: usize,
+}
+
+//The CaseChange Enum holds how the text after a \U, \L or \E operator in a replacement is cased
+#[derive(Clone, Copy)]
+enum CaseChange {
+    Upper,
+    Lower,
+    Keep,
+}
+
+//The CaseSegment Struct holds the part of a replacement up to the next case operator and how it is cased
+struct CaseSegment {
+    case: CaseChange,
+    template: String,
+}
+
+//The Substitution Struct holds one --sub rule, the regex to find and the text to replace it with
+struct Substitution {
+    find: Regex,
+    replacement: String,
+}
+
+//The Output Struct holds where formatted output lines go, they are either printed straight away or buffered to be sorted
+struct Output {
+    sort_order: Option<SortOrder>,
+    buffered_lines: Vec<String>,
+    json_results: Option<Vec<FileResult>>,
+    context_printed: bool,
+    max_memory: Option<usize>,
+    buffered_bytes: usize,
+    patch: String,
+    json_open_file: Option<String>,
+    seen_lines: Option<HashSet<String>>,
+    head: Option<usize>,
+    tail: Option<usize>,
+    tail_lines: VecDeque<String>,
+    silent: bool,
+    record_terminator: &'static str,
+    file_records: Option<Vec<String>>,
+}
+
+//The FileSearch Struct holds everything searching one file on a walker thread produced, until it is added to the run in order
+struct FileSearch {
+    output: Output,
+    totals: RunTotals,
+    pending_writes: Vec<(String, Vec<u8>)>,
+    elapsed: Duration,
+    result: Result<(), String>,
+}
+
+//A FoundFile is a path -r found with what visiting it gave, or the error mesage for a path it could not read
+type FoundFile = Result<(String, Option<FileSearch>), String>;
+
+//The FoundFiles Struct holds the files -r is done with that were found after a file it is still busy with, so each file is given out in the order it was found
+struct FoundFiles {
+    receiver: mpsc::Receiver<(usize, FoundFile)>,
+    next_index: usize,
+    waiting: BTreeMap<usize, FoundFile>,
+    waiting_bytes: usize,
+    max_memory: Option<usize>,
+}
+
+//The LineChange Struct holds a line changed by an in place edit for --patch-out, new_line is None for deleted lines
+struct LineChange {
+    line_number: usize,
+    old_line: String,
+    new_line: Option<String>,
+}
+
+//The FileResult Struct holds every match found in one file for --json-pretty
+#[derive(Serialize)]
+struct FileResult {
+    path: String,
+    matches: Vec<JsonMatch>,
+}
+
+//The JsonMatch Struct holds a matching line and where in it the pattern was found
+#[derive(Serialize)]
+struct JsonMatch {
+    line_number: usize,
+    line: String,
+    submatches: Vec<JsonSubmatch>,
+}
+
+//The JsonEvent Enum holds one line of the --json stream, its kind is written as the type field like ripgrep's
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum JsonEvent<'a> {
+    Begin {
+        path: &'a str,
+    },
+    Match {
+        path: &'a str,
+        #[serde(flatten)]
+        json_match: JsonMatch,
+    },
+    End {
+        path: &'a str,
+        matched_lines: usize,
+    },
+    Summary {
+        matched_lines: usize,
+        files_searched: usize,
+        files_matched: usize,
+        lines_scanned: usize,
+        bytes_scanned: usize,
+        elapsed_seconds: f64,
+    },
+}
+
+//The JsonCount Struct holds the number of matches in one file for --count-format json, path is left out without filenames
+#[derive(Serialize)]
+struct JsonCount<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a str>,
+    count: usize,
+}
+
+//The JsonSubmatch Struct holds a single match within a line, start and end are byte offsets
+#[derive(Serialize)]
+struct JsonSubmatch {
+    text: String,
+    start: usize,
+    end: usize,
+}
+
+//The IndexEntry Struct holds the trigrams found in one file for --index, along with the size and modification time they were read at
+#[derive(Serialize, Deserialize)]
+struct IndexEntry {
+    size: u64,
+    modified: Duration,
+    trigrams: Vec<u32>,
+}
+
+//The TrigramIndex Struct holds the --index loaded from disk, keyed by the canonical path of each file
+struct TrigramIndex {
+    path: String,
+    files: HashMap<String, IndexEntry>,
+    changed: bool,
+}
+
+impl RunTotals {
+    /*
+    Breif Explanation: Creates the totals of a run, or of one file searched on a walker thread, before anything is searched.
+
+    Parameters: 
+        config: &Config - instance of a config struct that holds the --sub rules and --per-pattern-stats patterns.
+
+    Returns: 
+        RunTotals - every count at 0.
+    */
+    fn new(config: &Config) -> RunTotals {
+        RunTotals {
+            matches: 0,
+            replacements: 0,
+            sub_counts: vec![0; config.subs.len()],
+            pattern_lines: vec![0; config.pattern_stats.len()],
+            pattern_files: vec![0; config.pattern_stats.len()],
+            group_counts: HashMap::new(),
+            time_buckets: BTreeMap::new(),
+            lines_scanned: 0,
+            bytes_scanned: 0,
+            files_searched: 0,
+            files_matched: 0,
+        }
+    }
+
+    /*
+    Breif Explanation: Adds the counts of a file searched on a walker thread to the totals of the run.
+
+    Parameters: 
+        file_totals: RunTotals - the counts of the file.
+
+    Returns: NA
+    */
+    fn add(&mut self, file_totals: RunTotals) {
+        self.matches += file_totals.matches;
+        self.replacements += file_totals.replacements;
+        for (total, count) in self.sub_counts.iter_mut().zip(file_totals.sub_counts) {
+            *total += count;
+        }
+        for (total, count) in self.pattern_lines.iter_mut().zip(file_totals.pattern_lines) {
+            *total += count;
+        }
+        for (total, count) in self.pattern_files.iter_mut().zip(file_totals.pattern_files) {
+            *total += count;
+        }
+        for (value, count) in file_totals.group_counts {
+            *self.group_counts.entry(value).or_insert(0) += count;
+        }
+        for (bucket, count) in file_totals.time_buckets {
+            *self.time_buckets.entry(bucket).or_insert(0) += count;
+        }
+        self.lines_scanned += file_totals.lines_scanned;
+        self.bytes_scanned += file_totals.bytes_scanned;
+        self.files_searched += file_totals.files_searched;
+        self.files_matched += file_totals.files_matched;
+    }
+}
+
+impl Output {
+    /*
+    Breif Explanation: Creates where the output of a run goes, when sorting or printing a JSON document all output is held in memory until every file has been searched.
+
+    Parameters: 
+        config: &Config - instance of a config struct that holds the output options.
+
+    Returns: 
+        Output - nothing printed or held yet.
+    */
+    fn new(config: &Config) -> Output {
+        Output {
+            sort_order: config.sort_output,
+            buffered_lines: Vec::new(),
+            json_results: if config.json_pretty {
+                Some(Vec::new())
+            } else {
+                None
+            },
+            context_printed: false,
+            max_memory: config.max_memory,
+            buffered_bytes: 0,
+            patch: String::new(),
+            json_open_file: None,
+            seen_lines: if config.unique_lines {
+                Some(HashSet::new())
+            } else {
+                None
+            },
+            head: config.head,
+            tail: config.tail,
+            tail_lines: VecDeque::new(),
+            silent: config.silent_scan || config.summary_only,
+            // a NUL can not be part of a line of text, so records holding the newlines of --multiline matches stay apart
+            record_terminator: if config.print0_lines { "\0" } else { "\n" },
+            file_records: None,
+        }
+    }
+
+    /*
+    Breif Explanation: Prints an output line, or holds on to it until the end of the run when sorting.
+
+    Parameters: 
+        line: String - the fully formatted output line.
+
+    Returns: NA
+    */
+    fn emit(&mut self, line: String) {
+        // a file searched on a walker thread keeps its lines until every file found before it has been printed,
+        // so like other output held back they count against --max-memory
+        if let Some(file_records) = &mut self.file_records {
+            self.buffered_bytes += line.len();
+            check_memory_limit(self.max_memory, self.buffered_bytes, "-r");
+            file_records.push(line);
+            return;
+        }
+        // --silent-scan searches every file for the exit code alone
+        if self.silent {
+            return;
+        }
+        // --head counts down the lines still to be printed and --tail only keeps the last ones
+        match &mut self.head {
+            Some(0) => return,
+            Some(head) => *head -= 1,
+            None => (),
+        }
+        if let Some(tail) = self.tail {
+            if self.tail_lines.len() == tail {
+                self.tail_lines.pop_front();
+            }
+            if tail > 0 {
+                self.tail_lines.push_back(line);
+            }
+            return;
+        }
+        if self.sort_order.is_some() {
+            self.track_buffered(line.len(), "--sort-output");
+            self.buffered_lines.push(line);
+        } else {
+            print_record(&line, self.record_terminator);
+        }
+    }
+
+    /*
+    Breif Explanation: Adds a matching line to the results printed as one JSON document by --json-pretty.
+
+    Parameters: 
+        file_path: &String - the file path for the associated file.
+        json_match: JsonMatch - the matching line.
+
+    Returns: NA
+    */
+    fn add_json_match(&mut self, file_path: &String, json_match: JsonMatch) {
+        if self.json_results.is_none() {
+            return;
+        }
+        let match_bytes = json_match.line.len()
+            + json_match
+                .submatches
+                .iter()
+                .map(|submatch| submatch.text.len())
+                .sum::<usize>();
+        self.track_buffered(match_bytes, "--json-pretty");
+        let json_results = match &mut self.json_results {
+            Some(json_results) => json_results,
+            None => return,
+        };
+        // matches arrive one file at a time so only the last file result can belong to this file
+        match json_results.last_mut() {
+            Some(file_result) if &file_result.path == file_path => {
+                file_result.matches.push(json_match)
+            }
+            _ => json_results.push(FileResult {
+                path: file_path.to_string(),
+                matches: vec![json_match],
+            }),
+        }
+    }
+
+    /*
+    Breif Explanation: Prints a matching line as a match event of the --json stream, after a begin event for its file.
+
+    Parameters: 
+        file_path: &String - the file path for the associated file.
+        json_match: JsonMatch - the matching line.
+
+    Returns: NA
+    */
+    fn add_json_event(&mut self, file_path: &String, json_match: JsonMatch) {
+        // like ripgrep a file only gets begin and end events once it has a match
+        if self.json_open_file.as_ref() != Some(file_path) {
+            self.emit(json_event_line(&JsonEvent::Begin { path: file_path }));
+            self.json_open_file = Some(file_path.clone());
+        }
+        self.emit(json_event_line(&JsonEvent::Match {
+            path: file_path,
+            json_match,
+        }));
+    }
+
+    /*
+    Breif Explanation: Prints the end event of the --json stream for the file that was just searched, if it had a begin event.
+
+    Parameters: 
+        matched_lines: usize - the number of matching lines in the file.
+
+    Returns: NA
+    */
+    fn end_json_file(&mut self, matched_lines: usize) {
+        if let Some(path) = self.json_open_file.take() {
+            self.emit(json_event_line(&JsonEvent::End {
+                path: &path,
+                matched_lines,
+            }));
+        }
+    }
+
+    /*
+    Breif Explanation: Adds the changes made to one file to the unified diff written by --patch-out.
+
+    Parameters: 
+        file_path: &String - the file path for the edited file.
+        changes: &[LineChange] - every changed line of the file in order.
+
+    Returns: NA
+    */
+    fn add_patch(&mut self, file_path: &String, changes: &[LineChange]) {
+        let diff = unified_diff(file_path, changes);
+        self.track_buffered(diff.len(), "--patch-out");
+        self.patch.push_str(&diff);
+    }
+
+    /*
+    Breif Explanation: Remembers a matching line for --unique-lines.
+
+    Parameters: 
+        line: &str - the matching line.
+
+    Returns: 
+        true if the line has not been printed before in the run, or --unique-lines was not given, and false otherwise.
+    */
+    fn first_sighting(&mut self, line: &str) -> bool {
+        let new_line = match &mut self.seen_lines {
+            Some(seen_lines) => seen_lines.insert(line.to_string()),
+            None => return true,
+        };
+        // every distinct line is kept until the end of the run
+        if new_line {
+            self.track_buffered(line.len(), "--unique-lines");
+        }
+        new_line
+    }
+
+    /*
+    Breif Explanation: Counts data held in memory until the end of the run against --max-memory.
+
+    Parameters: 
+        bytes: usize - the approximate size of the data being held on to.
+        what: &str - the option that needs the data buffered, used in the error message.
+
+    Returns: NA
+    */
+    fn track_buffered(&mut self, bytes: usize, what: &str) {
+        self.buffered_bytes += bytes;
+        self.check_memory(0, what);
+    }
+
+    /*
+    Breif Explanation: Stops the run when buffered data plus a temporary buffer would go over --max-memory.
+
+    Parameters: 
+        bytes: usize - the size of a temporary buffer that is about to be used, 0 for none.
+        what: &str - the option that needs the data buffered, used in the error message.
+
+    Returns: NA
+    */
+    fn check_memory(&self, bytes: usize, what: &str) {
+        check_memory_limit(self.max_memory, self.buffered_bytes + bytes, what);
+    }
+
+    /*
+    Breif Explanation: Checks if --head has printed all of the lines it allows, so the search can stop early.
+
+    Parameters: NA
+
+    Returns: 
+        true if no more lines will be printed and false otherwise.
+    */
+    fn head_reached(&self) -> bool {
+        self.head == Some(0)
+    }
+
+    /*
+    Breif Explanation: Prints any buffered output lines in the requested order.
+
+    Parameters: NA
+
+    Returns: NA
+    */
+    fn finish(&mut self) {
+        match self.sort_order {
+            Some(SortOrder::Ascending) => self.buffered_lines.sort(),
+            Some(SortOrder::Descending) => self.buffered_lines.sort_by(|a, b| b.cmp(a)),
+            None => (),
+        }
+        for line in self
+            .buffered_lines
+            .drain(..)
+            .chain(self.tail_lines.drain(..))
+        {
+            print_record(&line, self.record_terminator);
+        }
+        if let Some(json_results) = self.json_results.take() {
+            match serde_json::to_string_pretty(&json_results) {
+                Ok(json) => print_line(&json),
+                Err(_) => print_error("Could not write JSON output"),
+            }
+        }
+    }
+}
+
+impl Iterator for FoundFiles {
+    type Item = FoundFile;
+
+    /*
+    Breif Explanation: Waits for the next file in the order the files were found, holding on to files that are done before it.
+
+    Parameters: NA
+
+    Returns: 
+        Some(FoundFile) - the next file and what visiting it gave, or the error mesage for a path that could not be read.
+        None - every file has been found and given out.
+    */
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(found_file) = self.waiting.remove(&self.next_index) {
+                self.next_index += 1;
+                self.waiting_bytes -= held_bytes(&found_file);
+                return Some(found_file);
+            }
+            // the walk is over once every thread that could still find a file is gone
+            let (index, found_file) = self.receiver.recv().ok()?;
+            self.waiting_bytes += held_bytes(&found_file);
+            check_memory_limit(self.max_memory, self.waiting_bytes, "-r");
+            self.waiting.insert(index, found_file);
+        }
+    }
+}
+
+/*
+Breif Explanation: Stops the run when data held in memory would go over --max-memory.
+
+Parameters: 
+    max_memory: Option<usize> - the --max-memory limit, None for no limit.
+    bytes: usize - the size of everything held in memory with the data about to be held.
+    what: &str - the option that needs the data held, used in the error message.
+
+Returns: NA
+*/
+fn check_memory_limit(max_memory: Option<usize>, bytes: usize, what: &str) {
+    if let Some(max_memory) = max_memory {
+        if bytes > max_memory {
+            print_error(&format!(
+                "Error: {} needs more than the --max-memory limit of {} bytes, stopping",
+                what, max_memory
+            ));
+            process::exit(2);
+        }
+    }
+}
+
+/*
+Breif Explanation: Gives the size of the output a file searched on a walker thread is holding on to.
+
+Parameters: 
+    found_file: &FoundFile - a file found by -r.
+
+Returns: 
+    usize - the bytes of output and edited files held by the search, 0 for a file not searched yet or a path that could not be read.
+*/
+fn held_bytes(found_file: &FoundFile) -> usize {
+    match found_file {
+        Ok((_, Some(file_search))) => {
+            file_search.output.buffered_bytes
+                + file_search
+                    .pending_writes
+                    .iter()
+                    .map(|(_, new_content)| new_content.len())
+                    .sum::<usize>()
+        }
+        _ => 0,
+    }
 }
 
+impl TrigramIndex {
+    /*
+    Breif Explanation: Loads the index from a previous run, an index that does not exist yet starts out empty.
+
+    Parameters: 
+        path: &String - the file path of the index.
+
+    Returns: 
+        TrigramIndex - the loaded index.
+    */
+    fn load(path: &String) -> TrigramIndex {
+        let files = match fs::read_to_string(path) {
+            Ok(json) => match serde_json::from_str(&json) {
+                Ok(files) => Some(files),
+                Err(_) => {
+                    print_error(&format!(
+                        "--index: could not read the index {}, rebuilding it",
+                        path
+                    ));
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+        TrigramIndex {
+            path: path.clone(),
+            changed: files.is_none(),
+            files: files.unwrap_or_default(),
+        }
+    }
+
+    /*
+    Breif Explanation: Checks if a file could contain the pattern, indexing it first when it is new or changed since it was indexed.
+
+    Parameters: 
+        file_path: &String - the file path for a given file.
+        required: &[Vec<u32>] - the trigrams of each pattern, from pattern_trigrams.
+
+    Returns: 
+        bool - false only when the file is indexed and has not all the trigrams of any pattern.
+    */
+    fn might_match(&mut self, file_path: &String, required: &[Vec<u32>]) -> bool {
+        // standard input and files that can not be read are always searched, which also reports the error
+        let (key, metadata) = match (fs::canonicalize(file_path), fs::metadata(file_path)) {
+            (Ok(key), Ok(metadata)) => (key.display().to_string(), metadata),
+            _ => return true,
+        };
+        let modified = match metadata.modified() {
+            Ok(modified) => modified.duration_since(UNIX_EPOCH).unwrap_or_default(),
+            Err(_) => return true,
+        };
+        let fresh = self
+            .files
+            .get(&key)
+            .is_some_and(|entry| entry.size == metadata.len() && entry.modified == modified);
+        // a stale entry can not rule anything out so the file is read again for its trigrams
+        if !fresh {
+            let trigrams = match fs::read(file_path) {
+                Ok(content) => trigrams_of(&content, false),
+                Err(_) => return true,
+            };
+            let entry = IndexEntry {
+                size: metadata.len(),
+                modified,
+                trigrams: trigrams.into_iter().collect(),
+            };
+            self.files.insert(key.clone(), entry);
+            self.changed = true;
+        }
+        let trigrams = &self.files[&key].trigrams;
+        required.is_empty()
+            || required.iter().any(|needed| {
+                needed
+                    .iter()
+                    .all(|trigram| trigrams.binary_search(trigram).is_ok())
+            })
+    }
+
+    /*
+    Breif Explanation: Writes the index back to disk when files were added to it or indexed again.
+
+    Parameters: NA
+
+    Returns: NA
+    */
+    fn save(&self) {
+        if !self.changed {
+            return;
+        }
+        let written = match serde_json::to_string(&self.files) {
+            Ok(json) => fs::write(&self.path, json).is_ok(),
+            Err(_) => false,
+        };
+        if !written {
+            print_error(&format!("--index: could not write the index {}", self.path));
+        }
+    }
+}
 
 impl Config {
     // essentially the constructor for the Config struct
-    fn new(args: &Vec<String>) -> Result<Self, String> {
+    fn new(args: &[String]) -> Result<Self, String> {
         // default values set for config
         let mut config = Config {
             pattern: String::new(),
+            patterns: Vec::new(),
+            pattern_stats: Vec::new(),
             file_paths: Vec::new(),
             case_insensitive: false,
+            ascii_case: false,
+            no_unicode: false,
+            regex_mode: false,
+            literal_prefix: None,
+            word_regexp: false,
+            word_chars: None,
             print_line_numbers: false,
             invert_match: false,
             recursive_search: false,
+            exclude_dirs: Vec::new(),
+            git_diff_lines: None,
+            index: None,
+            clipboard: None,
             print_filenames: false,
+            no_filename: false,
+            path_display: None,
             colored_output: false,
+            highlight_line: false,
+            color_groups: false,
+            show_whitespace: false,
+            hyperlink: false,
+            max_total: None,
+            head: None,
+            tail: None,
+            max_count: None,
+            before_context: 0,
+            after_context: 0,
+            companion_offset: None,
+            only_matching: false,
+            json_escape: false,
+            normalize: None,
+            count: false,
+            rank: false,
+            list_sorted: false,
+            with_zero: false,
+            min_count: None,
+            count_total: false,
+            count_format: CountFormat::Plain,
+            group_by: None,
+            time_group: None,
+            time_regex: None,
+            unique_lines: false,
+            max_per_line: None,
+            max_line_length: None,
+            overlapping: false,
+            replace: None,
+            replace_case: Vec::new(),
+            replace_tokens: false,
+            if_match: None,
+            replace_max_per_line: None,
+            keep_indent: false,
+            delete_matches: false,
+            subs: Vec::new(),
+            in_place: false,
+            confirm: false,
+            confirm_summary: false,
+            max_changes: None,
+            transaction: false,
+            patch_out: None,
+            check: false,
+            preview: None,
+            strict_utf8: false,
+            keep_newline: false,
+            multiline: false,
+            column: false,
+            encoding_fallback: None,
+            verbose: false,
+            no_messages: false,
+            print0_lines: false,
+            silent_scan: false,
+            strict: false,
+            summary_only: false,
+            sort_output: None,
+            csv: false,
+            json_pretty: false,
+            json: false,
+            bytes: false,
+            search_compressed: false,
+            stats: false,
+            timings: false,
+            pager: false,
+            watch_roots: None,
+            max_memory: None,
+            regex_size_limit: None,
+            wrap_width: None,
+            field: None,
+            compare: false,
+            delimiter: '\t',
             help: false,
+            completions: None,
+            subcommand: Subcommand::Search,
         };
         //loop through args to try and update default values for option flags and non option flags get added to vector
         let mut non_options = Vec::new();
-        for (i, arg) in args.iter().enumerate() {
-            // the first arg only stores program name so skip it
-            if i == 0 {
-                continue;
-            } else {
-                match arg.as_str() {
-                    "-i" => config.case_insensitive = true,
-                    "-n" => config.print_line_numbers = true,
-                    "-v" => config.invert_match = true,
-                    "-r" => config.recursive_search = true,
-                    "-f" => config.print_filenames = true,
-                    "-c" => config.colored_output = true,
-                    "-h" | "--help" => config.help = true,
-                    // anything that is not a flag must be related to file path or pattern so push to non_options vector to be dealt with later.
-                    _ => non_options.push(arg.clone()),
+        let mut files_from = None;
+        let mut git_root = false;
+        let mut clipboard = false;
+        let mut git_diff = false;
+        let mut per_pattern_stats = false;
+        let mut count_files = false;
+        let mut prepend: Option<String> = None;
+        let mut append: Option<String> = None;
+        let mut wrap = false;
+        let mut watch = false;
+        let mut delimiter = None;
+        // like the --sub rules the --if-match condition is compiled once -i and -E are known
+        let mut if_match = None;
+        // the --time-regex is compiled once the whole command line has been read, like --if-match
+        let mut time_regex = None;
+        // --sub rules are compiled once every flag is known so -i applies to them too
+        let mut sub_rules = Vec::new();
+        // the first arg only stores program name so skip it, the one after may pick a subcommand
+        let option_args = match args.get(1).map(|arg| arg.as_str()) {
+            Some("search") => &args[2..],
+            Some("replace") => {
+                config.subcommand = Subcommand::Replace;
+                &args[2..]
+            }
+            _ => args.get(1..).unwrap_or(&[]),
+        };
+        let mut remaining_args = option_args.iter();
+        while let Some(arg) = remaining_args.next() {
+            if config.subcommand == Subcommand::Replace
+                && SEARCH_ONLY_OPTIONS.contains(&arg.as_str())
+            {
+                return Err(format!("Error: {} can not be used with replace", arg));
+            }
+            // an unknown long option would otherwise quietly be taken as the pattern or a file
+            if arg.starts_with("--")
+                && arg != "--"
+                && !OPTIONS.iter().any(|(option, _)| option == arg)
+            {
+                return Err(format!(
+                    "Error: unknown option {}, use -e to search for a pattern starting with -",
+                    arg
+                ));
+            }
+            match arg.as_str() {
+                "-i" => config.case_insensitive = true,
+                "--ascii-case" => config.ascii_case = true,
+                "--no-unicode" => config.no_unicode = true,
+                "-E" => config.regex_mode = true,
+                "--literal-prefix" => match remaining_args.next() {
+                    Some(prefix) => config.literal_prefix = Some(prefix.clone()),
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "-e" => match remaining_args.next() {
+                    Some(pattern) => config.patterns.push(pattern.clone()),
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "--per-pattern-stats" => per_pattern_stats = true,
+                "-w" => config.word_regexp = true,
+                "--word-chars" => match remaining_args.next() {
+                    Some(word_chars) => match Regex::new(&format!("^[{}]$", word_chars)) {
+                        Ok(word_chars) => config.word_chars = Some(word_chars),
+                        Err(_) => {
+                            return Err(format!("Error: invalid --word-chars set: {}", word_chars))
+                        }
+                    },
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "-n" => config.print_line_numbers = true,
+                "-v" => config.invert_match = true,
+                "-r" => config.recursive_search = true,
+                "--git-root" => git_root = true,
+                "--clipboard" => clipboard = true,
+                "--git-diff" => git_diff = true,
+                "--exclude-dir" => match remaining_args.next() {
+                    Some(exclude_dir) => match glob::Pattern::new(exclude_dir) {
+                        Ok(exclude_dir) => config.exclude_dirs.push(exclude_dir),
+                        Err(_) => return Err(format!("Error: invalid glob: {}", exclude_dir)),
+                    },
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "-f" => config.print_filenames = true,
+                "--no-filename" => config.no_filename = true,
+                "--path-display=relative" => config.path_display = Some(PathDisplay::Relative),
+                "--path-display=absolute" => config.path_display = Some(PathDisplay::Absolute),
+                "-c" => config.colored_output = true,
+                // unlike -c which leaves it to the terminal, --color=always colors even piped output
+                "--color=always" => {
+                    config.colored_output = true;
+                    colored::control::set_override(true);
+                }
+                "--color=never" => config.colored_output = false,
+                "--color=auto" => config.colored_output = stdout_is_terminal(),
+                "--highlight-line" => config.highlight_line = true,
+                "--color-groups" => config.color_groups = true,
+                "--show-whitespace" | "--highlight-whitespace" => config.show_whitespace = true,
+                "--hyperlink" => config.hyperlink = true,
+                "-h" | "--help" => config.help = true,
+                // options that take a value consume the next argument
+                "--max-matches-total" => {
+                    config.max_total = Some(parse_number_option(arg, remaining_args.next())?)
+                }
+                "-m" => config.max_count = Some(parse_number_option(arg, remaining_args.next())?),
+                "--head" => config.head = Some(parse_number_option(arg, remaining_args.next())?),
+                "--tail" => config.tail = Some(parse_number_option(arg, remaining_args.next())?),
+                "-A" => config.after_context = parse_number_option(arg, remaining_args.next())?,
+                "-B" => config.before_context = parse_number_option(arg, remaining_args.next())?,
+                "--companion-offset" => match remaining_args.next().map(|n| n.parse::<isize>()) {
+                    Some(Ok(0)) => {
+                        return Err(String::from("Error: --companion-offset can not be 0"))
+                    }
+                    Some(Ok(n)) => config.companion_offset = Some(n),
+                    Some(Err(_)) => return Err(format!("Error: {} requires a whole number", arg)),
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "-C" => {
+                    let context = parse_number_option(arg, remaining_args.next())?;
+                    config.before_context = context;
+                    config.after_context = context;
+                }
+                "-o" => config.only_matching = true,
+                "--json-escape" => config.json_escape = true,
+                "--normalize" => match remaining_args
+                    .next()
+                    .map(|form| form.to_uppercase())
+                    .as_deref()
+                {
+                    Some("NFC") => config.normalize = Some(NormalForm::Nfc),
+                    Some("NFD") => config.normalize = Some(NormalForm::Nfd),
+                    Some("NFKC") => config.normalize = Some(NormalForm::Nfkc),
+                    Some("NFKD") => config.normalize = Some(NormalForm::Nfkd),
+                    Some(form) => {
+                        return Err(format!(
+                            "Error: unknown --normalize form {}, use NFC, NFD, NFKC or NFKD",
+                            form
+                        ))
+                    }
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "--count" => config.count = true,
+                // ranking reuses the per file counts of --count, they are just printed at the end
+                "--rank" => {
+                    config.rank = true;
+                    config.count = true;
+                }
+                "--unique-lines" => config.unique_lines = true,
+                "--list-sorted" => {
+                    config.list_sorted = true;
+                    config.count = true;
+                }
+                // like -l and --count together, the counts are always shown next to their file
+                "--count-files" => {
+                    count_files = true;
+                    config.count = true;
+                    config.print_filenames = true;
+                }
+                // the tally of group values takes the place of the per file counts
+                "--group-by" => {
+                    config.group_by = Some(parse_number_option(arg, remaining_args.next())?);
+                    config.count = true;
                 }
+                // the histogram of matches over time also takes the place of the per file counts
+                "--time-group" => match remaining_args.next().map(|unit| unit.as_str()) {
+                    Some("minute") => {
+                        config.time_group = Some(TimeUnit::Minute);
+                        config.count = true;
+                    }
+                    Some("hour") => {
+                        config.time_group = Some(TimeUnit::Hour);
+                        config.count = true;
+                    }
+                    Some("day") => {
+                        config.time_group = Some(TimeUnit::Day);
+                        config.count = true;
+                    }
+                    Some(unit) => {
+                        return Err(format!(
+                            "Error: unknown --time-group {}, use minute, hour or day",
+                            unit
+                        ))
+                    }
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "--time-regex" => match remaining_args.next() {
+                    Some(pattern) => time_regex = Some(pattern.clone()),
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "--with-zero" => config.with_zero = true,
+                "--min-count" => {
+                    config.min_count = Some(parse_number_option(arg, remaining_args.next())?)
+                }
+                "--count-total" => config.count_total = true,
+                "--count-format" => match remaining_args.next().map(|style| style.as_str()) {
+                    Some("plain") => config.count_format = CountFormat::Plain,
+                    Some("kv") => config.count_format = CountFormat::KeyValue,
+                    Some("json") => config.count_format = CountFormat::Json,
+                    Some(style) => {
+                        return Err(format!(
+                            "Error: unknown --count-format {}, use plain, kv or json",
+                            style
+                        ))
+                    }
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "--overlapping" => config.overlapping = true,
+                "--max-per-line" => {
+                    config.max_per_line = Some(parse_number_option(arg, remaining_args.next())?)
+                }
+                "--max-line-length" => {
+                    config.max_line_length = Some(parse_number_option(arg, remaining_args.next())?)
+                }
+                "--replace" => match remaining_args.next() {
+                    Some(replacement) => {
+                        config.replace = Some(unescape_replacement(replacement));
+                        config.replace_case = case_segments(replacement);
+                    }
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "--if-match" => match remaining_args.next() {
+                    Some(condition) => if_match = Some(condition.clone()),
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "--prepend" => match remaining_args.next() {
+                    Some(text) => prepend = Some(text.clone()),
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "--append" => match remaining_args.next() {
+                    Some(text) => append = Some(text.clone()),
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "--replace-max-per-line" => {
+                    config.replace_max_per_line =
+                        Some(parse_number_option(arg, remaining_args.next())?)
+                }
+                "--keep-indent" => config.keep_indent = true,
+                "--delete-matches" => config.delete_matches = true,
+                "--sub" => match remaining_args.next() {
+                    Some(rule) => sub_rules.push(rule.clone()),
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "--in-place" => config.in_place = true,
+                "--confirm" => config.confirm = true,
+                "--confirm-summary" => config.confirm_summary = true,
+                "--transaction" => config.transaction = true,
+                "--check" => config.check = true,
+                "--patch-out" => match remaining_args.next() {
+                    Some(patch_path) => config.patch_out = Some(patch_path.clone()),
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "--max-changes" => {
+                    config.max_changes = Some(parse_number_option(arg, remaining_args.next())?)
+                }
+                "--preview" => {
+                    config.preview = Some(parse_number_option(arg, remaining_args.next())?)
+                }
+                "--strict-utf8" => config.strict_utf8 = true,
+                "-U" | "--multiline" => config.multiline = true,
+                "--column" => config.column = true,
+                "--keep-newline" => config.keep_newline = true,
+                "--encoding-fallback" => match remaining_args.next() {
+                    Some(name) => match Encoding::for_label(name.as_bytes()) {
+                        Some(encoding) => config.encoding_fallback = Some(encoding),
+                        None => return Err(format!("Error: unknown encoding: {}", name)),
+                    },
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "--verbose" => config.verbose = true,
+                "-S" | "--no-messages" => config.no_messages = true,
+                "--print0-lines" => config.print0_lines = true,
+                "--silent-scan" => config.silent_scan = true,
+                "--strict" => config.strict = true,
+                "--sort-output" => config.sort_output = Some(SortOrder::Ascending),
+                "--sort-output=reverse" => config.sort_output = Some(SortOrder::Descending),
+                "--csv" => config.csv = true,
+                "--json-pretty" => config.json_pretty = true,
+                "--json" => config.json = true,
+                "--bytes" => config.bytes = true,
+                "-z" | "--search-zip" | "--search-compressed" => config.search_compressed = true,
+                "--stats" => config.stats = true,
+                // the matches are still counted for the --stats block, they are just never printed
+                "--summary-only" => {
+                    config.summary_only = true;
+                    config.stats = true;
+                }
+                "--timings" => config.timings = true,
+                "--pager" => config.pager = true,
+                "--watch" => watch = true,
+                "--wrap" => wrap = true,
+                "--compare" => config.compare = true,
+                "--field" => config.field = Some(parse_number_option(arg, remaining_args.next())?),
+                "--delimiter" => match remaining_args.next() {
+                    Some(value) => delimiter = Some(value.clone()),
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "--max-memory" => {
+                    config.max_memory = Some(parse_size_option(arg, remaining_args.next())?)
+                }
+                "--regex-size-limit" => {
+                    config.regex_size_limit = Some(parse_size_option(arg, remaining_args.next())?)
+                }
+                "--index" => match remaining_args.next() {
+                    Some(index_path) => config.index = Some(index_path.clone()),
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                // picked up by main before parsing so errors while parsing go to the same place
+                "--errors-to-stdout" => (),
+                "--generate-completions" => match remaining_args.next() {
+                    Some(shell) => config.completions = Some(shell.clone()),
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                "--files-from" => match remaining_args.next() {
+                    Some(manifest) => files_from = Some(manifest.clone()),
+                    None => return Err(format!("Error: {} requires a value", arg)),
+                },
+                // anything that is not a flag must be related to file path or pattern so push to non_options vector to be dealt with later.
+                _ => non_options.push(arg.clone()),
             }
         }
-        if config.help {
+        if config.help || config.completions.is_some() {
             return Ok(config);
         }
-
-        if non_options.is_empty() {
-            return Err(String::from("Error: No pattern provided"));
+        // replace takes the replacement as the argument right after the pattern, or first when -e gives the patterns
+        if config.subcommand == Subcommand::Replace {
+            let replacement_index = if config.patterns.is_empty() { 1 } else { 0 };
+            if non_options.len() <= replacement_index {
+                return Err(String::from(
+                    "Error: replace requires a pattern and a replacement",
+                ));
+            }
+            let replacement = non_options.remove(replacement_index);
+            config.replace = Some(unescape_replacement(&replacement));
+            config.replace_case = case_segments(&replacement);
         }
-        if non_options.len() < 2 {
-            return Err(String::from("Error: No file paths provided"));
+        // the match itself is kept, so --prepend and --append are a replacement of $0 with text around it
+        if prepend.is_some() || append.is_some() {
+            if config.replace.is_some() || config.delete_matches || !sub_rules.is_empty() {
+                return Err(String::from(
+                    "Error: --prepend and --append can not be used with --replace, --delete-matches or --sub",
+                ));
+            }
+            config.replace = Some(wrap_replacement(
+                prepend.as_deref().unwrap_or(""),
+                append.as_deref().unwrap_or(""),
+            ));
+        }
+        // {file} and {line} are filled in for every line before the replacement is made
+        config.replace_tokens = config.replace.as_ref().is_some_and(|replacement| {
+            replacement.contains("{file}") || replacement.contains("{line}")
+        });
+        if config.keep_indent && config.replace.is_none() {
+            return Err(String::from("Error: --keep-indent requires --replace"));
+        }
+        match config.replace_max_per_line {
+            Some(_) if config.replace.is_none() => {
+                return Err(String::from(
+                    "Error: --replace-max-per-line requires --replace",
+                ))
+            }
+            Some(0) => {
+                return Err(String::from(
+                    "Error: --replace-max-per-line must be at least 1",
+                ))
+            }
+            _ => (),
+        }
+        if let Some(pattern) = time_regex {
+            if config.time_group.is_none() {
+                return Err(String::from("Error: --time-regex requires --time-group"));
+            }
+            match Regex::new(&pattern) {
+                Ok(time_regex) => config.time_regex = Some(time_regex),
+                Err(_) => return Err(format!("Error: invalid --time-regex pattern: {}", pattern)),
+            }
+        }
+        if let Some(condition) = if_match {
+            if config.replace.is_none() {
+                return Err(String::from("Error: --if-match requires --replace"));
+            }
+            let condition = if config.regex_mode {
+                condition
+            } else {
+                regex::escape(&condition)
+            };
+            match RegexBuilder::new(&condition)
+                .case_insensitive(config.case_insensitive)
+                .build()
+            {
+                Ok(condition) => config.if_match = Some(condition),
+                Err(_) => return Err(format!("Error: invalid --if-match pattern: {}", condition)),
+            }
+        }
+        for rule in &sub_rules {
+            config
+                .subs
+                .push(parse_substitution(rule, config.case_insensitive)?);
+        }
+        if !config.subs.is_empty() && (config.replace.is_some() || config.delete_matches) {
+            return Err(String::from(
+                "Error: --sub can not be used with --replace or --delete-matches",
+            ));
+        }
+        // escape sequences would garble piped output so hyperlinks are only used for colored terminal output
+        if config.hyperlink && !(config.colored_output && stdout_is_terminal()) {
+            config.hyperlink = false;
+        }
+        if config.compare
+            && (config.changes_lines()
+                || config.count
+                || config.only_matching
+                || config.uses_context()
+                || config.csv
+                || config.json
+                || config.json_pretty
+                || config.bytes
+                || config.multiline)
+        {
+            return Err(String::from(
+                "Error: --compare can not be used with --replace, --sub, --delete-matches, --count, --rank, --list-sorted, -o, -A, -B, -C, --csv, --json, --json-pretty, --bytes or --multiline",
+            ));
+        }
+        // wrapping only makes sense when there is a terminal width to wrap to
+        if wrap && io::stdout().is_terminal() {
+            config.wrap_width = terminal_size::terminal_size().map(|(width, _)| width.0 as usize);
+        }
+        // the pager shows the colors on the terminal even though the output reaches it through a pipe
+        if config.colored_output && env::var_os(PAGED_ENV).is_some() {
+            colored::control::set_override(true);
+        }
+        if config.in_place && !config.changes_lines() {
+            return Err(String::from(
+                "Error: --in-place requires --replace, --sub or --delete-matches",
+            ));
+        }
+        if config.transaction && !config.in_place {
+            return Err(String::from("Error: --transaction requires --in-place"));
+        }
+        if config.max_changes.is_some() && !config.in_place {
+            return Err(String::from("Error: --max-changes requires --in-place"));
+        }
+        if config.confirm && !config.in_place {
+            return Err(String::from("Error: --confirm requires --in-place"));
+        }
+        if config.confirm_summary && !config.in_place {
+            return Err(String::from("Error: --confirm-summary requires --in-place"));
+        }
+        if config.patch_out.is_some() && !config.in_place {
+            return Err(String::from("Error: --patch-out requires --in-place"));
+        }
+        if config.check && (!config.changes_lines() || config.in_place) {
+            return Err(String::from(
+                "Error: --check requires --replace, --sub or --delete-matches and can not be used with --in-place",
+            ));
+        }
+        if config.strict && config.no_messages {
+            return Err(String::from(
+                "Error: --strict can not be used with --no-messages",
+            ));
+        }
+        // nothing is printed so there would be no way to see the changes made or the output asked for
+        if config.silent_scan
+            && (config.changes_lines() || config.csv || config.json_pretty || config.pager || watch)
+        {
+            return Err(String::from(
+                "Error: --silent-scan can not be used with --replace, --sub, --delete-matches, --csv, --json-pretty, --pager or --watch",
+            ));
+        }
+        if config.summary_only && (config.changes_lines() || config.csv || config.json_pretty) {
+            return Err(String::from(
+                "Error: --summary-only can not be used with --replace, --sub, --delete-matches, --csv or --json-pretty",
+            ));
+        }
+        // each group is colored on top of the match, the other ways of rebuilding matches would hide the groups
+        if config.color_groups
+            && (!config.regex_mode
+                || config.highlight_line
+                || config.overlapping
+                || config.word_chars.is_some()
+                || config.show_whitespace)
+        {
+            return Err(String::from(
+                "Error: --color-groups requires -E and can not be used with --highlight-line, --overlapping, --word-chars or --show-whitespace",
+            ));
+        }
+        if config.json_escape && !config.only_matching && config.replace.is_none() {
+            return Err(String::from(
+                "Error: --json-escape requires -o, --replace, --prepend or --append",
+            ));
+        }
+        if config.literal_prefix.is_some() && !config.regex_mode {
+            return Err(String::from(
+                "Error: --literal-prefix requires -E, without it the whole pattern is already literal",
+            ));
+        }
+        if config.normalize.is_some() && config.replace.is_none() {
+            return Err(String::from(
+                "Error: --normalize requires --replace, --prepend or --append",
+            ));
+        }
+        if config.max_per_line.is_some() && !config.only_matching {
+            return Err(String::from("Error: --max-per-line requires -o"));
+        }
+        if config.preview.is_some() {
+            if !config.changes_lines() {
+                return Err(String::from(
+                    "Error: --preview requires --replace, --sub or --delete-matches",
+                ));
+            }
+            // a preview only ever shows the changes, files are never modified
+            config.in_place = false;
+        }
+        if config.list_sorted && (config.count_total || config.with_zero || config.rank) {
+            return Err(String::from(
+                "Error: --list-sorted can not be used with --count-total, --with-zero or --rank",
+            ));
+        }
+        if config.group_by.is_some()
+            && (!config.regex_mode
+                || config.invert_match
+                || config.rank
+                || config.list_sorted
+                || count_files
+                || config.with_zero
+                || config.count_total
+                || config.bytes
+                || config.multiline)
+        {
+            return Err(String::from(
+                "Error: --group-by requires -E and can not be used with -v, --rank, --list-sorted, --count-files, --with-zero, --count-total, --bytes or --multiline",
+            ));
+        }
+        if config.time_group.is_some()
+            && (config.group_by.is_some()
+                || config.rank
+                || config.list_sorted
+                || count_files
+                || config.with_zero
+                || config.count_total
+                || config.bytes
+                || config.multiline)
+        {
+            return Err(String::from(
+                "Error: --time-group can not be used with --group-by, --rank, --list-sorted, --count-files, --with-zero, --count-total, --bytes or --multiline",
+            ));
+        }
+        // only whole matching lines are remembered, so there is nothing to leave out for the other kinds of output
+        if config.unique_lines
+            && (!config.prints_whole_lines()
+                || config.before_context > 0
+                || config.after_context > 0
+                || config.companion_offset.is_some()
+                || config.changes_lines()
+                || config.bytes
+                || config.multiline)
+        {
+            return Err(String::from(
+                "Error: --unique-lines can not be used with -o, -A, -B, -C, --companion-offset, --count, --csv, --json, --json-pretty, --replace, --sub, --delete-matches, --bytes or --multiline",
+            ));
+        }
+        // both count the printed lines, so every printed line has to be a match
+        if config.head.is_some() || config.tail.is_some() {
+            if config.head.is_some() && config.tail.is_some() {
+                return Err(String::from("Error: --head can not be used with --tail"));
+            }
+            if config.before_context > 0
+                || config.after_context > 0
+                || config.companion_offset.is_some()
+                || config.count
+                || config.csv
+                || config.json
+                || config.json_pretty
+                || config.in_place
+                || config.sort_output.is_some()
+            {
+                return Err(String::from(
+                    "Error: --head and --tail can not be used with -A, -B, -C, --companion-offset, --count, --rank, --list-sorted, --csv, --json, --json-pretty, --in-place or --sort-output",
+                ));
+            }
+        }
+        if config.count_format != CountFormat::Plain
+            && (!config.count || config.group_by.is_some() || config.time_group.is_some())
+        {
+            return Err(String::from(
+                "Error: --count-format requires --count, --rank or --count-files",
+            ));
+        }
+        if count_files && (config.with_zero || config.no_filename) {
+            return Err(String::from(
+                "Error: --count-files can not be used with --with-zero or --no-filename",
+            ));
+        }
+        if config.count_total && !config.count {
+            return Err(String::from(
+                "Error: --count-total requires --count or --rank",
+            ));
+        }
+        if config.with_zero && !config.count {
+            return Err(String::from(
+                "Error: --with-zero requires --count or --rank",
+            ));
+        }
+        if config.min_count.is_some()
+            && (!config.count
+                || config.with_zero
+                || config.list_sorted
+                || config.group_by.is_some()
+                || config.time_group.is_some())
+        {
+            return Err(String::from(
+                "Error: --min-count requires --count, --rank or --count-files and can not be used with --with-zero",
+            ));
+        }
+        if config.count && (config.changes_lines() || config.csv || config.json_pretty) {
+            return Err(String::from(
+                "Error: --count, --rank and --list-sorted can not be used with --replace, --sub, --delete-matches, --csv or --json-pretty",
+            ));
+        }
+        if config.companion_offset.is_some()
+            && (config.before_context > 0 || config.after_context > 0)
+        {
+            return Err(String::from(
+                "Error: --companion-offset can not be used with -A, -B or -C",
+            ));
+        }
+        if config.word_chars.is_some() && !config.word_regexp {
+            return Err(String::from("Error: --word-chars requires -w"));
+        }
+        if config.bytes {
+            if config.word_chars.is_some() {
+                return Err(String::from(
+                    "Error: --bytes can not be used with --word-chars",
+                ));
+            }
+            if config.colored_output {
+                return Err(String::from(
+                    "Error: --bytes can not be used with colored output",
+                ));
+            }
+            if config.changes_lines() || config.csv {
+                return Err(String::from(
+                    "Error: --bytes can not be used with --replace, --sub, --delete-matches or --csv",
+                ));
+            }
+            if config.overlapping {
+                return Err(String::from(
+                    "Error: --bytes can not be used with --overlapping",
+                ));
+            }
+        }
+        // like cut, fields are counted from 1 and split on tabs unless another delimiter is given
+        if let Some(delimiter) = delimiter {
+            if config.field.is_none() {
+                return Err(String::from("Error: --delimiter requires --field"));
+            }
+            let mut chars = unescape_replacement(&delimiter)
+                .chars()
+                .collect::<Vec<char>>();
+            if chars.len() != 1 {
+                return Err(format!(
+                    "Error: --delimiter must be a single character, got {}",
+                    delimiter
+                ));
+            }
+            config.delimiter = chars.remove(0);
+        }
+        if config.field == Some(0) {
+            return Err(String::from(
+                "Error: --field counts from 1, the first field is --field 1",
+            ));
+        }
+        if config.field.is_some()
+            && (config.only_matching || config.csv || config.json_pretty || config.multiline)
+        {
+            return Err(String::from(
+                "Error: --field can not be used with -o, --csv, --json-pretty or --multiline",
+            ));
+        }
+        if config.csv && config.changes_lines() {
+            return Err(String::from(
+                "Error: --csv can not be used with --replace, --sub or --delete-matches",
+            ));
+        }
+        // the whole file is matched at once so there are no single lines to skip
+        if config.max_line_length.is_some() && config.multiline {
+            return Err(String::from(
+                "Error: --max-line-length can not be used with --multiline",
+            ));
+        }
+        if config.column && !config.multiline {
+            return Err(String::from("Error: --column requires --multiline"));
+        }
+        // a match spanning lines has no single line to invert, print context around or run line based changes on
+        if config.multiline
+            && (config.invert_match
+                || config.only_matching
+                || config.uses_context()
+                || config.companion_offset.is_some()
+                || !config.subs.is_empty()
+                || config.delete_matches
+                || config.bytes
+                || config.keep_newline
+                || config.csv
+                || config.json_pretty)
+        {
+            return Err(String::from(
+                "Error: --multiline can not be used with -v, -o, -A, -B, -C, --companion-offset, --sub, --delete-matches, --bytes, --keep-newline, --csv or --json-pretty",
+            ));
+        }
+        // with --multiline the replacement is made across the whole file, there are no lines to check or keep
+        // and no way to print only some of the changes
+        if config.multiline
+            && config.replace.is_some()
+            && (config.if_match.is_some()
+                || config.max_count.is_some()
+                || config.max_total.is_some()
+                || config.preview.is_some()
+                || config.replace_max_per_line.is_some()
+                || config.keep_indent
+                || config.confirm
+                || config.patch_out.is_some()
+                || config.column
+                || config.replace_tokens)
+        {
+            return Err(String::from(
+                "Error: --multiline with --replace can not be used with --if-match, -m, --max-matches-total, --preview, --replace-max-per-line, --keep-indent, --confirm, --patch-out, --column or {file} and {line} in the replacement",
+            ));
+        }
+        if config.json_pretty && (config.changes_lines() || config.bytes) {
+            return Err(String::from(
+                "Error: --json-pretty can not be used with --replace, --sub, --delete-matches or --bytes",
+            ));
+        }
+        // every event is printed as soon as it happens so nothing can be sorted or printed in between
+        if config.json
+            && (config.json_pretty
+                || config.csv
+                || config.count
+                || config.changes_lines()
+                || config.bytes
+                || config.multiline
+                || config.field.is_some()
+                || config.sort_output.is_some())
+        {
+            return Err(String::from(
+                "Error: --json can not be used with --json-pretty, --csv, --count, --rank, --list-sorted, --replace, --sub, --delete-matches, --bytes, --multiline, --field or --sort-output",
+            ));
+        }
+        // escape sequences have no place in CSV fields or JSON strings
+        if config.csv || config.json_pretty || config.json {
+            config.colored_output = false;
+        }
+        if config.delete_matches && config.replace.is_some() {
+            return Err(String::from(
+                "Error: --delete-matches can not be used with --replace",
+            ));
+        }
+
+        // the --sub rules or -e patterns take the place of the pattern so every non option is a file path
+        let first_file = if config.subs.is_empty() && config.patterns.is_empty() {
+            1
+        } else {
+            0
+        };
+        if per_pattern_stats && (!config.subs.is_empty() || config.bytes || config.multiline) {
+            return Err(String::from(
+                "Error: --per-pattern-stats can not be used with --sub, --bytes or --multiline",
+            ));
+        }
+        if non_options.is_empty() && first_file == 1 {
+            return Err(String::from("Error: No pattern provided"));
+        }
+        if git_root && non_options.len() > first_file {
+            return Err(String::from(
+                "Error: --git-root searches the whole repository so no file paths can be given",
+            ));
+        }
+        // the clipboard takes the place of every file, it is searched like standard input
+        if clipboard {
+            if non_options.len() > first_file || files_from.is_some() || git_root {
+                return Err(String::from(
+                    "Error: --clipboard searches the clipboard so no file paths, --files-from or --git-root can be given",
+                ));
+            }
+            if config.recursive_search || config.in_place || watch {
+                return Err(String::from(
+                    "Error: --clipboard can not be used with -r, --in-place or --watch",
+                ));
+            }
+            config.clipboard = Some(read_clipboard()?);
+        }
+        // like grep, input piped in without any file paths is searched as standard input
+        let read_stdin =
+            non_options.len() <= first_file && files_from.is_none() && !git_root && !clipboard;
+        if read_stdin && (io::stdin().is_terminal() || config.recursive_search) {
+            return Err(String::from("Error: No file paths provided"));
+        }
+        // ASSUMPTION: pattern will not be empty and will be correctly be input ahead of file paths
+        if first_file == 1 {
+            config.pattern = non_options[0].clone();
+        }
+        config.file_paths = if read_stdin || clipboard {
+            vec![String::from("-")]
+        } else {
+            expand_globs(&non_options[first_file..])?
+        };
+        // the whole repository is searched recursively, leaving out git's own files
+        if git_root {
+            config.file_paths = vec![find_git_root()?];
+            config.recursive_search = true;
+            match glob::Pattern::new(".git") {
+                Ok(git_dir) => config.exclude_dirs.push(git_dir),
+                Err(_) => return Err(String::from("Error: invalid --exclude-dir glob: .git")),
+            }
+        }
+        // without a repository to diff against there is nothing to narrow the search down to
+        if git_diff {
+            if config.bytes || config.multiline {
+                return Err(String::from(
+                    "Error: --git-diff can not be used with --bytes or --multiline",
+                ));
+            }
+            match git_changed_lines() {
+                Ok(changed_lines) => config.git_diff_lines = Some(changed_lines),
+                Err(e) => print_error(&format!("{}, searching every line", e)),
+            }
+        }
+        // the manifest is only read once, a list piped in on stdin can not be read a second time
+        let listed_files = match &files_from {
+            Some(manifest) => read_files_from(manifest)?,
+            None => Vec::new(),
+        };
+        // the paths are watched as they were given so files created in a directory later are searched too
+        if watch {
+            if read_stdin {
+                return Err(String::from(
+                    "Error: --watch needs file paths to watch, it can not watch standard input",
+                ));
+            }
+            // every search is run again by a copy of the program, which would find standard input already read
+            if files_from.as_deref() == Some("-") {
+                return Err(String::from(
+                    "Error: --watch can not be used with --files-from -, give the list as a file instead",
+                ));
+            }
+            // files changed by the search itself would start it again straight away
+            if config.in_place || config.index.is_some() || config.pager || config.confirm {
+                return Err(String::from(
+                    "Error: --watch can not be used with --in-place, --index, --pager or --confirm",
+                ));
+            }
+            let mut watch_roots = config.file_paths.clone();
+            watch_roots.extend(listed_files.iter().cloned());
+            config.watch_roots = Some(watch_roots);
+        }
+        // with -r the directories are only walked once the search starts so a path that can not be read
+        // is reported like a file that could not be searched, a listed file is searched as is
+        config.file_paths.extend(listed_files);
+        // each pattern gets a regex of its own so the lines and files it matched can be counted separately
+        if per_pattern_stats {
+            let patterns = if config.patterns.is_empty() {
+                vec![config.pattern.clone()]
+            } else {
+                config.patterns.clone()
+            };
+            let mut pattern_stats = Vec::new();
+            for pattern in &patterns {
+                pattern_stats.push(build_regex(
+                    &config,
+                    &pattern_source(&config, std::slice::from_ref(pattern)),
+                )?);
+            }
+            config.pattern_stats = pattern_stats;
+        }
+        Ok(config)
+    }
+
+    /*
+    Breif Explanation: Checks if the count of a file is printed, files without matches need --with-zero and none can be below --min-count.
+
+    Parameters: 
+        count: usize - the number of matches in the file.
+
+    Returns: 
+        true if the count is printed and false otherwise.
+    */
+    fn count_shown(&self, count: usize) -> bool {
+        (count > 0 || self.with_zero) && self.min_count.is_none_or(|min_count| count >= min_count)
+    }
+
+    /*
+    Breif Explanation: Checks if the text of each match and capture group is changed before it goes into the replacement.
+
+    Parameters: NA
+
+    Returns: 
+        true for --json-escape and --normalize and false otherwise.
+    */
+    fn transforms_matches(&self) -> bool {
+        self.json_escape || self.normalize.is_some()
+    }
+
+    /*
+    Breif Explanation: Checks if matching lines are changed, by --replace, --sub or --delete-matches.
+
+    Parameters: NA
+
+    Returns: 
+        true if matching lines are changed and false otherwise.
+    */
+    fn changes_lines(&self) -> bool {
+        self.replace.is_some() || self.delete_matches || !self.subs.is_empty()
+    }
+
+    /*
+    Breif Explanation: Names the kind of change counted when editing files, used in the change summaries.
+
+    Parameters: 
+        count: usize - how many changes are being named, 1 takes the singular.
+
+    Returns: 
+        &str - "lines deleted" for --delete-matches and "replacements" otherwise, or "line deleted" and "replacement" for one change.
+    */
+    fn change_noun(&self, count: usize) -> &str {
+        match (self.delete_matches, count) {
+            (true, 1) => "line deleted",
+            (true, _) => "lines deleted",
+            (false, 1) => "replacement",
+            (false, _) => "replacements",
+        }
+    }
+
+    /*
+    Breif Explanation: Checks if a --preview has already shown as many changes as were asked for.
+
+    Parameters: 
+        changes: usize - the number of changes shown so far in the run.
+
+    Returns: 
+        true if the preview limit has been reached and false otherwise.
+    */
+    fn preview_reached(&self, changes: usize) -> bool {
+        self.preview.is_some_and(|preview| changes >= preview)
+    }
+
+    /*
+    Breif Explanation: Checks if more changes have been made than --max-changes allows.
+
+    Parameters: 
+        changes: usize - the number of changes made so far in the run.
+
+    Returns: 
+        true if the limit has been passed and false otherwise.
+    */
+    fn max_changes_exceeded(&self, changes: usize) -> bool {
+        self.max_changes
+            .is_some_and(|max_changes| changes > max_changes)
+    }
+
+    /*
+    Breif Explanation: Checks if context lines are printed around matches, only whole matching lines get context.
+
+    Parameters: NA
+
+    Returns: 
+        true if -A, -B or -C was given and matching lines are printed as they are, false otherwise.
+    */
+    fn uses_context(&self) -> bool {
+        (self.before_context > 0 || self.after_context > 0) && self.prints_whole_lines()
+    }
+
+    /*
+    Breif Explanation: Checks if the files found by -r are searched on the threads walking the directories, which needs every file to be searched on its own.
+
+    Parameters: NA
+
+    Returns: 
+        true if files are searched by the threads that found them and false if they are searched one at a time in the order they were found,
+        for options that stop the run early, carry over from one file to the next or ask questions.
+    */
+    fn searches_in_parallel(&self) -> bool {
+        self.recursive_search
+            && self.max_total.is_none()
+            && self.head.is_none()
+            && self.preview.is_none()
+            && self.max_changes.is_none()
+            && self.index.is_none()
+            && !self.unique_lines
+            && !self.uses_context()
+            && !self.confirm
+            && !self.compare
+    }
+
+    /*
+    Breif Explanation: Checks if matching lines are printed as whole lines, which is when context and companion lines make sense.
+
+    Parameters: NA
+
+    Returns: 
+        true if matching lines are printed as they are and false otherwise.
+    */
+    fn prints_whole_lines(&self) -> bool {
+        !(self.in_place
+            || self.count
+            || self.csv
+            || self.json_pretty
+            || self.json
+            || self.only_matching)
+    }
+
+    /*
+    Breif Explanation: Decides if output lines are prefixed with the file they came from.
+
+    Parameters: NA
+
+    Returns: 
+        true if filenames should be printed and false otherwise.
+    */
+    fn show_filenames(&self) -> bool {
+        // like grep show which file a match came from whenever more than one file could be searched,
+        // -f forces filenames on for a single file and --no-filename always turns them off
+        if self.no_filename {
+            false
+        } else {
+            self.print_filenames || self.recursive_search || self.file_paths.len() > 1
+        }
+    }
+}
+
+/*
+Breif Explanation: Builds the regex used to find the pattern from the search options.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds search options.
+    pattern: &str - the regular expression source, from search_pattern.
+
+Returns: 
+    Ok(Regex) - the compiled regex.
+    Err(String) - Error mesage if the pattern could not be compiled.
+*/
+fn build_regex(config: &Config, pattern: &str) -> Result<Regex, String> {
+    #[cfg(test)]
+    tests::REGEX_BUILDS.with(|builds| builds.set(builds.get() + 1));
+    //case insensitity passed from config struct
+    //unicode enabled to esnure valid UTF-8 matches, unless --ascii-case asks for the faster ASCII only case folding
+    let ascii_case = config.case_insensitive && config.ascii_case;
+    //--no-unicode turns it off whether or not -i is used, for the smallest and fastest regex on ASCII input
+    let ascii_only = ascii_case || config.no_unicode;
+    let mut builder = RegexBuilder::new(pattern);
+    //with --multiline the whole file is matched at once so ^ and $ still mean the start and end of a line,
+    //replacing across lines also lets . match newlines so a block can be collapsed with a pattern like start.*?end
+    builder
+        .case_insensitive(config.case_insensitive)
+        .unicode(!ascii_only)
+        .multi_line(config.multiline)
+        .dot_matches_new_line(config.multiline && config.replace.is_some());
+    //both the compiled program and the lazy DFA built while matching are bounded by --regex-size-limit
+    if let Some(limit) = config.regex_size_limit {
+        builder.size_limit(limit).dfa_size_limit(limit);
+    }
+    match builder.build() {
+        Ok(re) => Ok(re),
+        Err(regex::Error::CompiledTooBig(limit)) => Err(regex_too_big(limit)),
+        //without unicode a pattern like . could match half of a multi byte character
+        Err(_) if config.no_unicode => Err(String::from(
+            "Could not create regex builder for pattern, with --no-unicode it must not use Unicode classes like \\p{L} or be able to match non ASCII bytes, like . can",
+        )),
+        Err(_) if ascii_case => Err(String::from(
+            "Could not create regex builder for pattern, with --ascii-case it must not be able to match non ASCII bytes",
+        )),
+        Err(_) => Err(String::from("Could not create regex builder for pattern")),
+    }
+}
+
+/*
+Breif Explanation: Builds the regex used to find the pattern in raw bytes for --bytes.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds search options.
+
+Returns: 
+    Ok(regex::bytes::Regex) - the compiled regex.
+    Err(String) - Error mesage if the pattern could not be compiled.
+*/
+fn build_byte_regex(config: &Config) -> Result<regex::bytes::Regex, String> {
+    let pattern = search_pattern(config);
+    //unicode disabled so escapes like \xFF match that exact byte rather than a UTF-8 encoded character
+    let mut builder = regex::bytes::RegexBuilder::new(&pattern);
+    builder
+        .case_insensitive(config.case_insensitive)
+        .unicode(false);
+    if let Some(limit) = config.regex_size_limit {
+        builder.size_limit(limit).dfa_size_limit(limit);
+    }
+    match builder.build() {
+        Ok(re) => Ok(re),
+        Err(regex::Error::CompiledTooBig(limit)) => Err(regex_too_big(limit)),
+        Err(_) => Err(String::from("Could not create regex builder for pattern")),
+    }
+}
+
+/*
+Breif Explanation: Gives the error for a pattern that compiles to a regex bigger than the size limit.
+
+Parameters: 
+    limit: usize - the size limit in bytes the regex went over.
+
+Returns: 
+    String - Error mesage saying the pattern is too big and how to raise the limit.
+*/
+fn regex_too_big(limit: usize) -> String {
+    format!(
+        "Could not create regex builder for pattern, it compiles to more than the {} byte limit, raise it with --regex-size-limit",
+        limit
+    )
+}
+
+/*
+Breif Explanation: Gives the regular expression source for the pattern, or every -e pattern, from the search options.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds search options.
+
+Returns: 
+    String - the regular expression source matching any of the patterns.
+*/
+fn search_pattern(config: &Config) -> String {
+    if config.patterns.is_empty() {
+        pattern_source(config, std::slice::from_ref(&config.pattern))
+    } else {
+        pattern_source(config, &config.patterns)
+    }
+}
+
+/*
+Breif Explanation: Gives the regular expression source matching any of the given patterns.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds search options.
+    patterns: &[String] - the patterns as given on the command line.
+
+Returns: 
+    String - the patterns as is in regex mode, after the escaped --literal-prefix if there is one, otherwise with all
+        regular expression meta characters escaped, joined as alternatives and wrapped in word boundaries for -w.
+*/
+fn pattern_source(config: &Config, patterns: &[String]) -> String {
+    let sources: Vec<String> = patterns
+        .iter()
+        .map(|pattern| {
+            // the prefix is grouped apart from the pattern so it comes before every alternative in it
+            if let Some(prefix) = config.literal_prefix.as_ref().filter(|_| config.regex_mode) {
+                format!("{}(?:{})", regex::escape(prefix), pattern)
+            } else if config.regex_mode {
+                pattern.clone()
+            } else {
+                regex::escape(pattern)
+            }
+        })
+        .collect();
+    //each pattern is grouped so an alternation inside one of them does not leak into the others
+    let pattern = if sources.len() == 1 {
+        sources[0].clone()
+    } else {
+        sources
+            .iter()
+            .map(|source| format!("(?:{})", source))
+            .collect::<Vec<String>>()
+            .join("|")
+    };
+    //like grep -w a match must not have a word character right before or after it,
+    //a custom --word-chars set is checked on each match instead
+    if config.word_regexp && config.word_chars.is_none() {
+        format!(r"\b{{start-half}}(?:{})\b{{end-half}}", pattern)
+    } else {
+        pattern
+    }
+}
+
+/*
+Breif Explanation: Makes sure the capture group --group-by counts exists in the pattern.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds search options.
+    re: &Regex - the compiled regex pattern.
+
+Returns: 
+    Ok(()) - the group exists, or --group-by was not given.
+    Err(String) - Error mesage saying how many groups the pattern has.
+*/
+fn validate_group_by(config: &Config, re: &Regex) -> Result<(), String> {
+    match config.group_by {
+        // captures_len includes group 0, the whole match
+        Some(group) if group >= re.captures_len() => Err(format!(
+            "Error: --group-by {} refers to a group the pattern does not have, it has {} capture groups",
+            group,
+            re.captures_len() - 1
+        )),
+        _ => Ok(()),
+    }
+}
+
+/*
+Breif Explanation: Makes sure any capture groups the replacement refers to actually exist in the pattern.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds search options.
+    re: &Regex - the compiled regex pattern.
+
+Returns: 
+    Ok(()) - every referenced group exists, or there is no replacement.
+    Err(String) - Error mesage naming the first group that does not exist.
+*/
+fn validate_replacement_groups(config: &Config, re: &Regex) -> Result<(), String> {
+    let replacement = match &config.replace {
+        Some(replacement) => replacement,
+        None => return Ok(()),
+    };
+    for group in replacement_group_references(replacement) {
+        // a literal pattern has no capture groups, only $0 for the whole match
+        if !config.regex_mode && group != "0" {
+            return Err(format!(
+                "Error: replacement refers to capture group {} but capture groups need -E regex mode",
+                group
+            ));
+        }
+        let group_exists = match group.parse::<usize>() {
+            Ok(index) => index < re.captures_len(),
+            Err(_) => re.capture_names().any(|name| name == Some(group.as_str())),
+        };
+        if !group_exists {
+            return Err(format!(
+                "Error: replacement refers to capture group {} which is not in the pattern",
+                group
+            ));
+        }
+    }
+    Ok(())
+}
+
+/*
+Breif Explanation: Finds the capture groups a replacement refers to with $1, $name or ${name}.
+
+Parameters: 
+    replacement: &str - the replacement text.
+
+Returns: 
+    Vec<String> - the group numbers and names referred to, $$ is a literal $ and is skipped.
+*/
+fn replacement_group_references(replacement: &str) -> Vec<String> {
+    let mut groups = Vec::new();
+    let mut rest = replacement;
+    while let Some(dollar) = rest.find('$') {
+        rest = &rest[dollar + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            rest = after;
+        } else if let Some(braced) = rest.strip_prefix('{') {
+            // an unclosed brace is treated as literal text by the regex crate
+            if let Some(close) = braced.find('}') {
+                groups.push(braced[..close].to_string());
+                rest = &braced[close + 1..];
+            }
+        } else {
+            let name_length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            if name_length > 0 {
+                groups.push(rest[..name_length].to_string());
+            }
+            rest = &rest[name_length..];
+        }
+    }
+    groups
+}
+
+/*
+Breif Explanation: Parses the number given as the value of an option flag.
+
+Parameters: 
+    flag: &str - the option flag the value belongs to, used in error messages.
+    value: Option<&String> - the argument following the option flag if there is one.
+
+Returns: 
+    Ok(usize) - the parsed number.
+    Err(String) - Error mesage if the value is missing or not a number.
+*/
+fn parse_number_option(flag: &str, value: Option<&String>) -> Result<usize, String> {
+    let value = match value {
+        Some(value) => value,
+        None => return Err(format!("Error: {} requires a value", flag)),
+    };
+    match value.parse::<usize>() {
+        Ok(number) => Ok(number),
+        Err(_) => Err(format!("Error: invalid number for {}: {}", flag, value)),
+    }
+}
+
+/*
+Breif Explanation: Parses a size given as the value of an option flag, like 4096, 512K, 64M or 1G.
+
+Parameters: 
+    flag: &str - the option flag the value belongs to, used in error messages.
+    value: Option<&String> - the argument following the option flag if there is one.
+
+Returns: 
+    Ok(usize) - the size in bytes.
+    Err(String) - Error mesage if the value is missing or not a size.
+*/
+fn parse_size_option(flag: &str, value: Option<&String>) -> Result<usize, String> {
+    let value = match value {
+        Some(value) => value,
+        None => return Err(format!("Error: {} requires a value", flag)),
+    };
+    let upper_value = value.to_uppercase();
+    let number = upper_value.strip_suffix('B').unwrap_or(&upper_value);
+    // the suffixes are powers of 1024 like most tools that take memory sizes
+    let (number, multiplier) = match number.chars().last() {
+        Some('K') => (&number[..number.len() - 1], 1024),
+        Some('M') => (&number[..number.len() - 1], 1024 * 1024),
+        Some('G') => (&number[..number.len() - 1], 1024 * 1024 * 1024),
+        _ => (number, 1),
+    };
+    match number.parse::<usize>() {
+        Ok(number) => Ok(number.saturating_mul(multiplier)),
+        Err(_) => Err(format!("Error: invalid size for {}: {}", flag, value)),
+    }
+}
+
+/*
+Breif Explanation: Expands path arguments containing glob meta characters, for shells that do not do it themselves.
+
+Parameters: 
+    paths: &[String] - the path arguments as given on the command line.
+
+Returns: 
+    Ok(Vec<String>) - the paths with every glob replaced by the paths it matches.
+    Err(String) - Error mesage if a glob is invalid or matches nothing.
+*/
+fn expand_globs(paths: &[String]) -> Result<Vec<String>, String> {
+    let mut expanded_paths = Vec::new();
+    for path in paths {
+        // only arguments that actually look like globs are expanded so other paths are left untouched
+        if !path.contains(['*', '?', '[']) {
+            expanded_paths.push(path.clone());
+            continue;
+        }
+        let matches = match glob::glob(path) {
+            Ok(matches) => matches,
+            Err(_) => return Err(format!("Error: invalid glob: {}", path)),
+        };
+        let expanded_count = expanded_paths.len();
+        for entry in matches {
+            match entry {
+                Ok(matched_path) => expanded_paths.push(matched_path.display().to_string()),
+                Err(_) => return Err(format!("Error: could not expand glob: {}", path)),
+            }
+        }
+        // like bash without nullglob a glob matching nothing is an error
+        if expanded_paths.len() == expanded_count {
+            return Err(format!("Error: no files match: {}", path));
+        }
+    }
+    Ok(expanded_paths)
+}
+
+/*
+Breif Explanation: Reads the paths to search from a manifest file, one path per line.
+
+Parameters: 
+    manifest: &String - path of the manifest file, or "-" to read the manifest from stdin.
+
+Returns: 
+    Ok(Vec<String>) - all listed files, blank lines are skipped.
+    Err(String) - Error mesage if the manifest can not be read.
+*/
+fn read_files_from(manifest: &String) -> Result<Vec<String>, String> {
+    let reader: Box<dyn BufRead> = if manifest == "-" {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        match File::open(manifest) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(_) => return Err(format!("Error: could not open file list: {}", manifest)),
+        }
+    };
+    let mut file_paths = Vec::new();
+    for line_result in reader.lines() {
+        let line = match line_result {
+            Ok(line) => line,
+            Err(_) => return Err(format!("Error: could not read file list: {}", manifest)),
+        };
+        let listed_path = line.trim_end_matches('\r');
+        if listed_path.is_empty() {
+            continue;
+        }
+        // a missing file is kept so it is reported on its own when it is searched, like a missing file given
+        // on the command line, the rest of the listed files are still searched and the run exits with 2
+        file_paths.push(listed_path.to_string());
+    }
+    Ok(file_paths)
+}
+
+/*
+Breif Explanation: Reads the text held in the system clipboard for --clipboard.
+
+Parameters: NA
+
+Returns: 
+    Ok(String) - the text in the clipboard.
+    Err(String) - Error mesage if there is no clipboard to read, like on a headless system, or it holds no text.
+*/
+fn read_clipboard() -> Result<String, String> {
+    let mut clipboard = match arboard::Clipboard::new() {
+        Ok(clipboard) => clipboard,
+        Err(e) => {
+            return Err(format!(
+                "Error: --clipboard could not access the clipboard: {}",
+                e
+            ))
+        }
+    };
+    match clipboard.get_text() {
+        Ok(text) => Ok(text),
+        Err(e) => Err(format!(
+            "Error: --clipboard could not read text from the clipboard: {}",
+            e
+        )),
+    }
+}
+
+/*
+Breif Explanation: Finds the top of the git repository the current directory is in by walking up to the first .git.
+
+Parameters: NA
+
+Returns: 
+    Ok(String) - the path of the directory holding .git.
+    Err(String) - Error mesage if the current directory is not inside a git repository.
+*/
+fn find_git_root() -> Result<String, String> {
+    let current_dir = match env::current_dir() {
+        Ok(current_dir) => current_dir,
+        Err(_) => return Err(String::from("Error: could not get the current directory")),
+    };
+    // .git is a file instead of a directory in worktrees and submodules
+    match current_dir
+        .ancestors()
+        .find(|directory| directory.join(".git").exists())
+    {
+        Some(git_root) => Ok(git_root.display().to_string()),
+        None => Err(format!(
+            "Error: --git-root could not find a git repository containing {}",
+            current_dir.display()
+        )),
+    }
+}
+
+/*
+Breif Explanation: Asks git which lines were added or changed in the working tree since HEAD, used for --git-diff.
+
+Parameters: NA
+
+Returns: 
+    Ok(HashMap<PathBuf, BTreeSet<usize>>) - the line numbers added to each changed file, keyed by the canonical path of the file.
+    Err(String) - Error mesage if git could not be run or the current directory is not in a repository with a commit.
+*/
+fn git_changed_lines() -> Result<HashMap<PathBuf, BTreeSet<usize>>, String> {
+    // without context lines every hunk covers only lines that were actually added
+    let git_output = Command::new("git")
+        .args([
+            "diff",
+            "-U0",
+            "--no-color",
+            "--no-ext-diff",
+            "--no-prefix",
+            "HEAD",
+        ])
+        .stderr(Stdio::null())
+        .output();
+    let git_output = match git_output {
+        Ok(git_output) if git_output.status.success() => git_output,
+        _ => {
+            return Err(String::from(
+                "--git-diff: not inside a git repository with a HEAD commit",
+            ))
+        }
+    };
+    // the paths in the diff are relative to the top of the repository
+    let git_root = match find_git_root() {
+        Ok(git_root) => PathBuf::from(git_root),
+        Err(_) => {
+            return Err(String::from(
+                "--git-diff: not inside a git repository with a HEAD commit",
+            ))
+        }
+    };
+    let mut changed_lines: HashMap<PathBuf, BTreeSet<usize>> = HashMap::new();
+    let mut current_file = None;
+    for line in String::from_utf8_lossy(&git_output.stdout).lines() {
+        if let Some(new_path) = line.strip_prefix("+++ ") {
+            // deleted files have no new side and paths with spaces are followed by a tab
+            current_file = fs::canonicalize(git_root.join(new_path.trim_end_matches('\t'))).ok();
+        } else if let (Some(hunk_header), Some(file)) = (line.strip_prefix("@@ "), &current_file) {
+            // the new side of a hunk is written +start,count, with the count left out when it is 1
+            let new_side = hunk_header
+                .split(' ')
+                .find_map(|range| range.strip_prefix('+'))
+                .unwrap_or("");
+            let (start, count) = match new_side.split_once(',') {
+                Some((start, count)) => (start.parse().unwrap_or(0), count.parse().unwrap_or(0)),
+                None => (new_side.parse().unwrap_or(0), 1),
+            };
+            changed_lines
+                .entry(file.clone())
+                .or_default()
+                .extend(start..start + count);
+        }
+    }
+    Ok(changed_lines)
+}
+
+/*
+Breif Explanation: Collects every run of three bytes in some text, with ASCII letters lower cased so one index serves -i too.
+
+Parameters: 
+    bytes: &[u8] - the text, a file or a pattern.
+    ascii_only: bool - skip runs with non ASCII bytes, whose case folding under -i the index can not follow.
+
+Returns: 
+    BTreeSet<u32> - the trigrams, each packed into the low three bytes of a u32.
+*/
+fn trigrams_of(bytes: &[u8], ascii_only: bool) -> BTreeSet<u32> {
+    bytes
+        .windows(3)
+        .filter(|window| !ascii_only || window.is_ascii())
+        .map(|window| {
+            window.iter().fold(0, |trigram, byte| {
+                trigram << 8 | u32::from(byte.to_ascii_lowercase())
+            })
+        })
+        .collect()
+}
+
+/*
+Breif Explanation: Gives the trigrams a file must contain to match each pattern, used to skip files with --index.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds search options.
+
+Returns: 
+    Vec<Vec<u32>> - the trigrams of each pattern, empty when the search can not be narrowed down by the index.
+*/
+fn pattern_trigrams(config: &Config) -> Vec<Vec<u32>> {
+    //regex patterns have no fixed text, and inverted or zero counts need the files without a match as well,
+    //compressed and re-encoded files hold other bytes than are matched
+    if config.regex_mode
+        || config.invert_match
+        || config.with_zero
+        || !config.subs.is_empty()
+        || config.search_compressed
+        || config.encoding_fallback.is_some()
+    {
+        return Vec::new();
+    }
+    let patterns = if config.patterns.is_empty() {
+        std::slice::from_ref(&config.pattern)
+    } else {
+        &config.patterns[..]
+    };
+    patterns
+        .iter()
+        .map(|pattern| {
+            trigrams_of(pattern.as_bytes(), config.case_insensitive)
+                .into_iter()
+                .collect()
+        })
+        .collect()
+}
+
+/*
+Breif Explanation: Finds all files in given directory on a thread of its own, each directory is walked on several threads at once and every file is visited by the thread that found it.
+
+Parameters: 
+    scope: &thread::Scope - the scope the walk runs in, it is over once every file has been found.
+    directories: &[String] - directory of all file paths to search.
+    exclude_dirs: &[glob::Pattern] - names of directories that are skipped along with everything in them.
+    max_memory: Option<usize> - the --max-memory limit for files visited before the files found ahead of them.
+    visit: impl Fn(&String) -> Option<FileSearch> - called with each file as soon as it is found, like searching it.
+
+Returns: 
+    FoundFiles - gives each file with what visit gave for it in the order the files were found, while the walk goes on,
+    with an Err holding the error mesage in place of each path that could not be read, the rest of the paths are still walked.
+*/
+fn recursively_find_all_files<'scope>(
+    scope: &'scope thread::Scope<'scope, '_>,
+    directories: &'scope [String],
+    exclude_dirs: &'scope [glob::Pattern],
+    max_memory: Option<usize>,
+    visit: impl Fn(&String) -> Option<FileSearch> + Send + Sync + 'scope,
+) -> FoundFiles {
+    let (sender, receiver) = mpsc::channel();
+    scope.spawn(move || {
+        // every file gets the next index as it is found, before it is visited, and the index decides the output order
+        let next_index = AtomicUsize::new(0);
+        let found = |found_file: Result<String, String>| {
+            let index = next_index.fetch_add(1, Ordering::Relaxed);
+            let found_file = found_file.map(|file_path| {
+                let visited = visit(&file_path);
+                (file_path, visited)
+            });
+            sender.send((index, found_file)).is_ok()
+        };
+        for directory in directories {
+            // - is standard input and not a path, so it is searched as it is and never walked
+            if directory == "-" {
+                found(Ok(directory.to_string()));
+                continue;
+            }
+            // get the metadata to see if file is actually a folder or not
+            let metadata = match fs::metadata(directory) {
+                Ok(metadata) => metadata,
+                Err(_) => {
+                    found(Err(format!("Could not get metadata for: {}", directory)));
+                    continue;
+                }
+            };
+            // if it is a file then it is found as it is
+            if metadata.is_file() {
+                found(Ok(directory.to_string()));
+            // if it is actually a directory walk through directory and find all files that are not hidden
+            } else if metadata.is_dir() {
+                // excluded directories are pruned before descending so nothing inside them is ever read,
+                // every file is walked so the .gitignore and hidden file rules of the walker are turned off
+                let exclude_dirs = exclude_dirs.to_vec();
+                let walker = WalkBuilder::new(directory)
+                    .standard_filters(false)
+                    .filter_entry(move |entry| {
+                        entry.depth() == 0
+                            || !entry
+                                .file_type()
+                                .is_some_and(|file_type| file_type.is_dir())
+                            || !exclude_dirs.iter().any(|exclude_dir| {
+                                exclude_dir.matches(&entry.file_name().to_string_lossy())
+                            })
+                    })
+                    .build_parallel();
+                // the directories are read on several threads at once, which matters for very large trees,
+                // a directory that can not be read is found as an error and the walk carries on
+                walker.run(|| {
+                    Box::new(|entry: Result<DirEntry, ignore::Error>| {
+                        let found_file = match entry {
+                            Ok(entry) => {
+                                let is_file = entry
+                                    .file_type()
+                                    .is_some_and(|file_type| file_type.is_file());
+                                let file_name = entry.file_name().to_str().unwrap_or("");
+                                if !is_file || file_name.starts_with(".") {
+                                    return WalkState::Continue;
+                                }
+                                Ok(entry.into_path().display().to_string())
+                            }
+                            Err(e) => Err(format!("Could not read directory: {}", e)),
+                        };
+                        // nothing is left to find the files for once the run stopped taking them
+                        if found(found_file) {
+                            WalkState::Continue
+                        } else {
+                            WalkState::Quit
+                        }
+                    })
+                });
+            }
+        }
+    });
+    FoundFiles {
+        receiver,
+        next_index: 0,
+        waiting: BTreeMap::new(),
+        waiting_bytes: 0,
+        max_memory,
+    }
+}
+
+fn main() {
+    // get the the command line arguments and use them to intialize an instance of Config struct
+    let passed_args: Vec<String> = env::args().collect();
+    ERRORS_TO_STDOUT.store(
+        passed_args.iter().any(|arg| arg == "--errors-to-stdout"),
+        Ordering::Relaxed,
+    );
+    // like grep a usage error, and below an invalid pattern, exits with 2 the same as a file that could not be searched
+    let config_set = match Config::new(&passed_args) {
+        Ok(config) => config,
+        Err(e) => {
+            print_error(&e);
+            display_help(true);
+            process::exit(2);
+        }
+    };
+    // if the user entered a help option flag print the help message and exit
+    if config_set.help {
+        match config_set.subcommand {
+            Subcommand::Search => display_help(false),
+            Subcommand::Replace => display_replace_help(),
+        }
+        return;
+    }
+    // the completion scripts are for the name the program was run as
+    if let Some(shell) = &config_set.completions {
+        let program = passed_args
+            .first()
+            .and_then(|program| Path::new(program).file_name())
+            .map_or(String::from("search_utility"), |program| {
+                program.to_string_lossy().to_string()
+            });
+        match generate_completions(shell, &program) {
+            Ok(script) => print_line(&script),
+            Err(e) => {
+                print_error(&e);
+                process::exit(2);
+            }
+        }
+        return;
+    }
+    // with --pager a copy of the program does the search while this one waits for the pager to be closed
+    if config_set.pager && io::stdout().is_terminal() {
+        match run_in_pager(&passed_args) {
+            Ok(exit_code) => process::exit(exit_code),
+            Err(e) => {
+                print_error(&e);
+                process::exit(2);
+            }
+        }
+    }
+    // the regex is compiled once here and shared by every file that is searched
+    let re = match build_regex(&config_set, &search_pattern(&config_set))
+        .and_then(|re| validate_replacement_groups(&config_set, &re).map(|_| re))
+        .and_then(|re| validate_group_by(&config_set, &re).map(|_| re))
+    {
+        Ok(re) => re,
+        Err(e) => {
+            print_error(&e);
+            process::exit(2);
+        }
+    };
+    // with --watch a copy of the program does each search while this one waits for the files to change
+    if let Some(watch_roots) = &config_set.watch_roots {
+        if let Err(e) = watch_and_rerun(&passed_args, watch_roots) {
+            print_error(&e);
+            process::exit(2);
+        }
+        return;
+    }
+    // go through all file paths and search through the file to find matches
+    // the totals are shared across files so --max-matches-total can stop the whole run
+    let mut totals = RunTotals::new(&config_set);
+    let mut output = Output::new(&config_set);
+    // --bytes searches raw bytes so it needs its own regex
+    let byte_re = if config_set.bytes {
+        match build_byte_regex(&config_set) {
+            Ok(byte_re) => Some(byte_re),
+            Err(e) => {
+                print_error(&e);
+                process::exit(2);
+            }
+        }
+    } else {
+        None
+    };
+    // with -r the directories are walked on a thread of their own while the files are searched here in the order
+    // they were found, a path that can not be read is found as an error so it is reported like a file that could
+    // not be searched and the other paths are still searched,
+    // when every file can be searched on its own it is searched by the thread that found it
+    let in_parallel = config_set.searches_in_parallel();
+    // how long each file took to search, only recorded with --timings
+    let mut file_timings: Vec<(String, Duration)> = Vec::new();
+    // any file that could not be searched makes the run exit with 2
+    let mut had_error = false;
+    // matching lines per file, only collected with --rank
+    let mut file_counts: Vec<(String, usize)> = Vec::new();
+    // files that matched, only collected with --list-sorted so each is printed once in order
+    let mut matched_files: BTreeSet<String> = BTreeSet::new();
+    // with --in-place nothing is written until every file has been searched and the total is known
+    let mut pending_writes: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut trigram_index = config_set.index.as_ref().map(TrigramIndex::load);
+    let required_trigrams = pattern_trigrams(&config_set);
+    let run_start = Instant::now();
+    let file_count = thread::scope(|scope| {
+        let mut found_files: Box<dyn Iterator<Item = FoundFile>> = if config_set.recursive_search {
+            Box::new(recursively_find_all_files(
+                scope,
+                &config_set.file_paths,
+                &config_set.exclude_dirs,
+                config_set.max_memory,
+                |file_path| {
+                    in_parallel.then(|| {
+                        search_on_walker_thread(file_path, &config_set, &re, byte_re.as_ref())
+                    })
+                },
+            ))
+        } else {
+            Box::new(
+                config_set
+                    .file_paths
+                    .iter()
+                    .map(|file_path| Ok((file_path.clone(), None))),
+            )
+        };
+        // --compare prints the matching lines of both files next to each other instead of one file after the other,
+        // there is only a left and a right column so any other number of files is searched as usual
+        if config_set.compare {
+            let found_paths: Vec<_> = found_files.collect();
+            if let [Ok((left_path, _)), Ok((right_path, _))] = found_paths.as_slice() {
+                match print_comparison(&config_set, [left_path, right_path], &re, &mut output) {
+                    Ok(matches) => {
+                        output.finish();
+                        process::exit(if matches == 0 { 1 } else { 0 });
+                    }
+                    Err(e) => {
+                        print_error(&e);
+                        process::exit(2);
+                    }
+                }
+            }
+            found_files = Box::new(found_paths.into_iter());
+        }
+        if config_set.csv {
+            print_line("path,line_number,column,match,line");
+        }
+        let mut file_count = 0;
+        for mut found_file in found_files.by_ref() {
+            file_count += 1;
+            let (file_path, file_search) = match &mut found_file {
+                Ok((file_path, file_search)) => (&*file_path, file_search.take()),
+                Err(e) => {
+                    had_error = true;
+                    report_file_error(&config_set, e);
+                    continue;
+                }
+            };
+            if config_set
+                .max_total
+                .is_some_and(|max_total| totals.matches >= max_total)
+                || config_set.preview_reached(totals.replacements)
+                || output.head_reached()
+                || config_set.max_changes_exceeded(totals.replacements)
+            {
+                break;
+            }
+            // files the index shows can not contain the pattern are never opened
+            if let Some(trigram_index) = &mut trigram_index {
+                if !trigram_index.might_match(file_path, &required_trigrams) {
+                    continue;
+                }
+            }
+            let matches_before = totals.matches;
+            let replacements_before = totals.replacements;
+            // a file already searched on a walker thread only has its results added now so they stay in order
+            let (search_result, elapsed) = match file_search {
+                Some(file_search) => {
+                    let elapsed = file_search.elapsed;
+                    (
+                        add_file_search(file_search, &mut totals, &mut output, &mut pending_writes),
+                        elapsed,
+                    )
+                }
+                None => {
+                    let search_start = Instant::now();
+                    let search_result = search_one_file(
+                        file_path,
+                        &config_set,
+                        &re,
+                        byte_re.as_ref(),
+                        &mut totals,
+                        &mut output,
+                        &mut pending_writes,
+                    );
+                    (search_result, search_start.elapsed())
+                }
+            };
+            totals.files_searched += 1;
+            if totals.matches > matches_before {
+                totals.files_matched += 1;
+            }
+            output.end_json_file(totals.matches - matches_before);
+            if config_set.timings {
+                file_timings.push((file_path.clone(), elapsed));
+            }
+            match search_result {
+                Ok(_) => {
+                    // the changes are reported once the file has been searched so they stay in the order of the files
+                    let file_replacements = totals.replacements - replacements_before;
+                    if config_set.changes_lines() && file_replacements > 0 {
+                        eprintln!(
+                            "{}: {} {}",
+                            file_path,
+                            file_replacements,
+                            config_set.change_noun(file_replacements)
+                        );
+                    } else if config_set.changes_lines() && config_set.verbose {
+                        eprintln!(
+                            "{}: no {}, file left unchanged",
+                            file_path,
+                            config_set.change_noun(0)
+                        );
+                    }
+                    if config_set.list_sorted {
+                        if totals.matches > matches_before {
+                            matched_files.insert(display_path(config_set.path_display, file_path));
+                        }
+                    } else if config_set.rank {
+                        file_counts.push((file_path.clone(), totals.matches - matches_before));
+                    } else if config_set.count
+                        && config_set.group_by.is_none()
+                        && config_set.time_group.is_none()
+                    {
+                        print_count(
+                            &config_set,
+                            &mut output,
+                            file_path,
+                            totals.matches - matches_before,
+                        );
+                    }
+                }
+                Err(e) => {
+                    had_error = true;
+                    report_file_error(&config_set, &e);
+                }
+            }
+        }
+        // the files after a run that stopped early are still counted
+        file_count + found_files.count()
+    });
+    if let Some(trigram_index) = &trigram_index {
+        trigram_index.save();
+    }
+    if config_set.max_changes_exceeded(totals.replacements) {
+        print_error(&format!(
+            "Error: aborted after {} {}, more than --max-changes {} allows, no files were changed",
+            totals.replacements,
+            config_set.change_noun(totals.replacements),
+            config_set.max_changes.unwrap_or(0)
+        ));
+        process::exit(2);
+    }
+    // the size of the whole batch is shown before any file is written and with --confirm-summary it is agreed
+    // to at once, a rolled back transaction has nothing left to show
+    let rolled_back = config_set.transaction && had_error;
+    if !pending_writes.is_empty() && !rolled_back {
+        eprintln!(
+            "{} {}, {} {}",
+            pending_writes.len(),
+            if pending_writes.len() == 1 {
+                "file"
+            } else {
+                "files"
+            },
+            totals.replacements,
+            config_set.change_noun(totals.replacements)
+        );
+        if config_set.confirm_summary && !ask_yes_no("apply these changes?") {
+            print_error("Error: aborted, no files were changed");
+            process::exit(1);
+        }
+    }
+    // a transaction only changes files once every file has been searched and written out successfully
+    if config_set.transaction {
+        if had_error {
+            print_error(
+                "Error: a file could not be searched so the transaction was rolled back, no files were changed",
+            );
+            process::exit(2);
+        }
+        if let Err(e) = commit_transaction(&pending_writes) {
+            print_error(&e);
+            process::exit(2);
+        }
+    } else {
+        for (file_path, new_content) in &pending_writes {
+            if let Err(e) = write_file_atomically(file_path, new_content) {
+                had_error = true;
+                print_error(&e);
+                if config_set.strict {
+                    process::exit(2);
+                }
+            }
+        }
+    }
+    // the patch is only written once the edits it describes have been made
+    if let Some(patch_path) = &config_set.patch_out {
+        if fs::write(patch_path, &output.patch).is_err() {
+            had_error = true;
+            print_error(&format!("Could not write patch file: {}", patch_path));
+        }
+    }
+    if config_set.rank {
+        print_ranking(&config_set, &mut output, &mut file_counts);
+    }
+    if config_set.group_by.is_some() {
+        print_group_counts(&mut output, &totals.group_counts);
+    }
+    if config_set.time_group.is_some() {
+        print_time_histogram(&mut output, &totals.time_buckets);
+    }
+    for matched_file in matched_files {
+        output.emit(matched_file);
+    }
+    // every matching line was counted for exactly one file so the sum is the run total
+    if config_set.count_total && file_count > 1 {
+        output.emit(match config_set.count_format {
+            CountFormat::Plain => format!("total: {}", totals.matches),
+            CountFormat::KeyValue => format!("total={}", totals.matches),
+            CountFormat::Json => format!("{{\"total\":{}}}", totals.matches),
+        });
+    }
+    if config_set.json {
+        output.emit(json_event_line(&JsonEvent::Summary {
+            matched_lines: totals.matches,
+            files_searched: totals.files_searched,
+            files_matched: totals.files_matched,
+            lines_scanned: totals.lines_scanned,
+            bytes_scanned: totals.bytes_scanned,
+            elapsed_seconds: run_start.elapsed().as_secs_f64(),
+        }));
+    }
+    output.finish();
+    if config_set.timings {
+        print_timings(&mut file_timings);
+    }
+    if config_set.stats {
+        print_stats(&config_set, &totals);
+    }
+    if !config_set.pattern_stats.is_empty() {
+        print_pattern_stats(&config_set, &totals);
+    }
+    if config_set.changes_lines() {
+        eprintln!(
+            "total: {} {}",
+            totals.replacements,
+            config_set.change_noun(totals.replacements)
+        );
+    }
+    // like grep exit with 2 when a file could not be searched and 1 when nothing matched
+    if had_error {
+        process::exit(2);
+    }
+    // for --check a change that would be made fails the run, like a linter finding a problem
+    if config_set.check {
+        process::exit(if totals.replacements > 0 { 1 } else { 0 });
+    }
+    if totals.matches == 0 {
+        process::exit(1);
+    }
+}
+
+/*
+Breif Explanation: Reports a file or directory that could not be searched, the rest of the files are still searched.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds the --no-messages and --strict options.
+    message: &str - the error message.
+
+Returns: NA
+*/
+fn report_file_error(config: &Config, message: &str) {
+    // with --no-messages it is skipped silently and with --strict the whole run stops,
+    // before any edited file held back by --in-place is written
+    if !config.no_messages {
+        print_error(message);
+    }
+    if config.strict {
+        process::exit(2);
+    }
+}
+
+/*
+Breif Explanation: Prints how long each file took to search to stderr, slowest first.
+
+Parameters: 
+    file_timings: &mut [(String, Duration)] - the file paths searched and how long each search took.
+
+Returns: NA
+*/
+fn print_timings(file_timings: &mut [(String, Duration)]) {
+    file_timings.sort_by_key(|timing| Reverse(timing.1));
+    for (file_path, duration) in file_timings.iter() {
+        eprintln!("{}: {:.3}ms", file_path, duration.as_secs_f64() * 1000.0);
+    }
+}
+
+/*
+Breif Explanation: Prints the summary of the whole run requested with --stats.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds the --sub rules and the --summary-only option.
+    totals: &RunTotals - counts accumulated across every file searched.
+
+Returns: NA
+*/
+fn print_stats(config: &Config, totals: &RunTotals) {
+    let match_percentage = if totals.files_searched == 0 {
+        0.0
+    } else {
+        totals.files_matched as f64 * 100.0 / totals.files_searched as f64
+    };
+    // the blank line only separates the summary from the matches printed before it
+    if !config.summary_only {
+        print_line("");
+    }
+    print_line(&format!("{} matched lines", totals.matches));
+    print_line(&format!(
+        "{} lines scanned ({} bytes)",
+        totals.lines_scanned, totals.bytes_scanned
+    ));
+    print_line(&format!(
+        "matched {} of {} files ({:.1}%)",
+        totals.files_matched, totals.files_searched, match_percentage
+    ));
+    for (sub, count) in config.subs.iter().zip(&totals.sub_counts) {
+        print_line(&format!("{} substitutions of {}", count, sub.find.as_str()));
+    }
+}
+
+/*
+Breif Explanation: Prints a line of output, once stdout has been closed (like a pager quitting early) the run stops quietly.
+
+Parameters: 
+    line: &str - the line to be printed.
+
+Returns: NA
+*/
+fn print_line(line: &str) {
+    print_record(line, "\n");
+}
+
+/*
+Breif Explanation: Prints an output record followed by its terminator, a newline or a NUL with --print0-lines.
+
+Parameters: 
+    record: &str - the record to be printed.
+    terminator: &str - what ends the record.
+
+Returns: NA
+*/
+fn print_record(record: &str, terminator: &str) {
+    match write!(io::stdout(), "{}{}", record, terminator) {
+        Ok(_) => (),
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => process::exit(0),
+        Err(_) => process::exit(2),
+    }
+}
+
+/*
+Breif Explanation: Prints an error message to stderr so it never mixes with the matches, or to stdout with --errors-to-stdout.
+
+Parameters: 
+    message: &str - the error message.
+
+Returns: NA
+*/
+fn print_error(message: &str) {
+    if ERRORS_TO_STDOUT.load(Ordering::Relaxed) {
+        print_line(message);
+    } else {
+        eprintln!("{message}");
+    }
+}
+
+/*
+Breif Explanation: Checks if output ends up on a terminal, either directly or through --pager.
+
+Parameters: NA
+
+Returns: 
+    true if stdout is a terminal or the output is being paged and false otherwise.
+*/
+fn stdout_is_terminal() -> bool {
+    io::stdout().is_terminal() || env::var_os(PAGED_ENV).is_some()
+}
+
+/*
+Breif Explanation: Runs the search again as a child process with its output piped into the pager, like git does with less.
+
+Parameters: 
+    args: &[String] - the command line arguments the program was started with.
+
+Returns: 
+    Ok(i32) - the exit code of the search.
+    Err(String) - Error mesage if the pager or the search could not be started.
+*/
+fn run_in_pager(args: &[String]) -> Result<i32, String> {
+    //$PAGER picks the pager, less -R is used otherwise so colors still show
+    let pager_command = match env::var("PAGER") {
+        Ok(pager_command) if !pager_command.trim().is_empty() => pager_command,
+        _ => String::from("less -R"),
+    };
+    let mut pager = match Command::new("sh")
+        .arg("-c")
+        .arg(&pager_command)
+        .stdin(Stdio::piped())
+        .spawn()
+    {
+        Ok(pager) => pager,
+        Err(_) => return Err(format!("Could not start pager: {}", pager_command)),
+    };
+    let pager_stdin = match pager.stdin.take() {
+        Some(pager_stdin) => pager_stdin,
+        None => return Err(format!("Could not write to pager: {}", pager_command)),
+    };
+    let program = match env::current_exe() {
+        Ok(program) => program,
+        Err(_) => return Err(String::from("Could not find the search_utility executable")),
+    };
+    // the command is dropped at the end of the block so the pager sees the end of its input
+    let search_status = {
+        let mut search = Command::new(program);
+        search
+            .args(args.iter().skip(1).filter(|arg| arg.as_str() != "--pager"))
+            .env(PAGED_ENV, "1")
+            .stdout(Stdio::from(pager_stdin));
+        search.status()
+    };
+    let _ = pager.wait();
+    match search_status {
+        Ok(search_status) => Ok(search_status.code().unwrap_or(2)),
+        Err(_) => Err(String::from("Could not run the search for the pager")),
+    }
+}
+
+/*
+Breif Explanation: Runs the search again in a copy of the program every time a watched file changes, for --watch.
+
+Parameters: 
+    args: &[String] - the command line arguments, passed on to each search without --watch.
+    watch_roots: &[String] - the files and directories given on the command line, directories are watched recursively.
+
+Returns: 
+    Ok(()) - the watcher stopped.
+    Err(String) - Error mesage if the files could not be watched or the search could not be started.
+*/
+fn watch_and_rerun(args: &[String], watch_roots: &[String]) -> Result<(), String> {
+    let program = match env::current_exe() {
+        Ok(program) => program,
+        Err(_) => return Err(String::from("Could not find the search_utility executable")),
+    };
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(sender) {
+        Ok(watcher) => watcher,
+        Err(e) => return Err(format!("Could not watch files: {}", e)),
+    };
+    let mut roots = Vec::new();
+    for watch_root in watch_roots {
+        let root = match fs::canonicalize(watch_root) {
+            Ok(root) => root,
+            Err(_) => return Err(format!("Could not watch {}", watch_root)),
+        };
+        // editors often save by writing a new file and renaming it over the old one, which ends a watch
+        // on the file itself, so the directory it is in is watched instead and the events filtered
+        let (watched, mode) = match root.parent() {
+            Some(parent) if !root.is_dir() => (parent.to_path_buf(), RecursiveMode::NonRecursive),
+            _ => (root.clone(), RecursiveMode::Recursive),
+        };
+        if let Err(e) = watcher.watch(&watched, mode) {
+            return Err(format!("Could not watch {}: {}", watch_root, e));
+        }
+        roots.push(root);
+    }
+    loop {
+        // the screen is only cleared on a terminal so piped output keeps every run
+        if io::stdout().is_terminal() {
+            print!("\x1b[2J\x1b[H");
+            let _ = io::stdout().flush();
+        }
+        let search_status = Command::new(&program)
+            .args(args.iter().skip(1).filter(|arg| arg.as_str() != "--watch"))
+            .status();
+        if search_status.is_err() {
+            return Err(String::from("Could not run the search for --watch"));
+        }
+        // wait for a change to one of the watched paths, reading the files during the search is not one
+        loop {
+            let event = match receiver.recv() {
+                Ok(Ok(event)) => event,
+                Ok(Err(e)) => return Err(format!("Could not watch files: {}", e)),
+                Err(_) => return Ok(()),
+            };
+            let relevant = !matches!(event.kind, EventKind::Access(_))
+                && event
+                    .paths
+                    .iter()
+                    .any(|path| roots.iter().any(|root| path.starts_with(root)));
+            if relevant {
+                break;
+            }
+        }
+        // the rest of the events from the same save are dropped until the files have been quiet for a moment
+        while receiver.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+    }
+}
+
+/*
+Breif Explanation: Prints how many lines and files each pattern matched for --per-pattern-stats.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds the patterns.
+    totals: &RunTotals - counts accumulated across every file searched.
+
+Returns: NA
+*/
+fn print_pattern_stats(config: &Config, totals: &RunTotals) {
+    let patterns = if config.patterns.is_empty() {
+        std::slice::from_ref(&config.pattern)
+    } else {
+        &config.patterns[..]
+    };
+    print_line("");
+    for (index, pattern) in patterns.iter().enumerate() {
+        let (lines, files) = (totals.pattern_lines[index], totals.pattern_files[index]);
+        print_line(&format!(
+            "{}: {} matched {} in {} {}",
+            pattern,
+            lines,
+            if lines == 1 { "line" } else { "lines" },
+            files,
+            if files == 1 { "file" } else { "files" }
+        ));
+    }
+}
+
+/*
+Breif Explanation: Builds a completion script for --generate-completions from the options in OPTIONS.
+
+Parameters: 
+    shell: &str - the shell the script is for, bash, zsh or fish.
+    program: &str - the name the program is run as.
+
+Returns: 
+    Ok(String) - the completion script.
+    Err(String) - Error mesage if the shell is not supported.
+*/
+fn generate_completions(shell: &str, program: &str) -> Result<String, String> {
+    let flags: Vec<&str> = OPTIONS.iter().map(|(option, _)| *option).collect();
+    // options given a path complete file names, other values have nothing to complete
+    let path_options: Vec<&str> = OPTIONS
+        .iter()
+        .filter(|(_, value)| *value == "<path>")
+        .map(|(option, _)| *option)
+        .collect();
+    let value_options: Vec<&str> = OPTIONS
+        .iter()
+        .filter(|(_, value)| !value.is_empty() && *value != "<path>")
+        .map(|(option, _)| *option)
+        .collect();
+    let function = format!(
+        "_{}",
+        program.replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+    );
+    let template = match shell {
+        "bash" => BASH_COMPLETION,
+        "zsh" => ZSH_COMPLETION,
+        "fish" => return Ok(fish_completions(program)),
+        _ => {
+            return Err(format!(
+                "Error: no completions for {}, the supported shells are bash, zsh and fish",
+                shell
+            ))
+        }
+    };
+    Ok(template
+        .replace("FUNCTION", &function)
+        .replace("PROGRAM", program)
+        .replace("PATH_OPTIONS", &path_options.join("|"))
+        .replace("VALUE_OPTIONS", &value_options.join("|"))
+        .replace("FLAGS", &flags.join(" ")))
+}
+
+/*
+Breif Explanation: Builds the fish completion script, which lists each option on its own line.
+
+Parameters: 
+    program: &str - the name the program is run as.
+
+Returns: 
+    String - the completion script.
+*/
+fn fish_completions(program: &str) -> String {
+    let mut lines = Vec::new();
+    for (option, value) in OPTIONS {
+        let argument = match *value {
+            "" => "",
+            "<path>" => " -r -F",
+            _ => " -x",
+        };
+        // the --name=value options are one option with a fixed set of values to fish
+        let line = match option.strip_prefix("--").map(|long| long.split_once('=')) {
+            Some(Some((long, choice))) => {
+                format!("complete -c {} -l {} -x -a {}", program, long, choice)
+            }
+            Some(None) => format!("complete -c {} -l {}{}", program, &option[2..], argument),
+            None => format!("complete -c {} -s {}{}", program, &option[1..], argument),
+        };
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+fn display_replace_help() {
+    let help = "Usage: grep replace [OPTIONS] <pattern> <replacement> <files...>
+       grep replace [OPTIONS] -e <pattern>... <replacement> <files...>
+
+Print matching lines with every match of pattern replaced by replacement, or edit the files with --in-place.
+$0 is the whole match and with -E $1 or ${name} refer to capture groups, \\n, \\t, \\r and \\\\ are turned
+into a newline, tab, carriage return and backslash. Like sed, \\U upper cases and \\L lower cases the rest of
+the replacement, groups included, until \\E or the next \\U or \\L, e.g. '\\U$1\\E_id' turns user into USER_id.
+{file} and {line} in the replacement are the path and number of the line being replaced, \\{ is a literal {.
+With several -e patterns the one replacement is used for the matches of every pattern.
+
+Options:
+-i                Case-insensitive search
+-E                Treat the pattern as a regular expression instead of literal text
+--literal-prefix <text>
+                  With -E, match text literally right before the pattern, so in --literal-prefix
+                  'config.json' -E ':[0-9]+' the . needs no escaping, with -e it comes before each pattern
+-e <pattern>      Replace the matches of pattern, can be given several times to replace the matches of
+                  any of them, with -E their capture groups are numbered across all of the patterns in
+                  order, so in -e '(a)' -e '(b)' the group of the second pattern is $2
+-w                Only replace whole words
+--word-chars <set>
+                  With -w, use the characters in the regex class set as word characters
+-n                Print line numbers
+-v                Invert match, only print the lines that do not match
+-r                Recursive directory search
+--git-root        Recursively search the whole git repository the current directory is in
+--git-diff        Only replace on lines added or changed in the working tree since HEAD
+--exclude-dir <glob>
+                  Skip directories whose name matches glob when searching recursively, can be repeated
+-m <n>            Stop replacing in a file after n matching lines
+--max-matches-total <n>
+                  Stop the whole run after n matching lines across all files
+--head <n>        Stop the whole run once n matching lines have been printed
+--tail <n>        Only print the last n matching lines once every file has been searched
+--if-match <pattern>
+                  Only replace on matching lines that also contain pattern
+--replace-max-per-line <n>
+                  Only replace the first n matches on each line
+--max-line-length <n>
+                  Leave lines longer than n characters as they are without matching them, --verbose
+                  prints every line that was skipped
+--keep-indent     Keep the spaces and tabs a matching line started with
+--in-place        Write the changed files back instead of printing the changed lines, once every file has
+                  been searched N files, M replacements is printed to stderr before any file is written
+--transaction     With --in-place, only change files if every file could be searched and written
+--max-changes <n> With --in-place, abort without changing any file if more than n changes would be made
+--confirm         With --in-place, ask on stderr before applying each change (y/n)
+--confirm-summary With --in-place, ask once (y/n) after N files, M replacements is printed before changing any file
+--preview <n>     Only show the first n changes and never modify files
+--check           Print the changes that would be made without modifying files, then exit with 1 if
+                  there were any and 0 if there were none
+--verbose         Print which files had no changes made to them to stderr
+--stats           Print a summary of the run
+-h, --help        Show this help information
+
+The output options of search (-c, --color, -f, --no-filename, --path-display, --sort-output, --pager, ...)
+work the same way here.";
+    print_line(help);
+}
+
+fn display_help(on_error: bool) {
+    let help = "Usage: grep [search] [OPTIONS] <pattern> <files...>
+       grep [search] [OPTIONS] -e <pattern>... <files...>
+       grep [search] [OPTIONS] --sub <find>=<replace>... <files...>
+       grep replace [OPTIONS] <pattern> <replacement> <files...>
+
+The search subcommand is used when none is given, so search for the word search with grep search search <files...>.
+Run grep replace --help for the options of the replace subcommand.
+
+A file of - reads from standard input, which is also searched when no files are given and input is piped in.
+With -r, - is still standard input and never a directory named -, use ./- for that.
+Devices such as /dev/null are searched as if they were empty and named pipes are read as a stream.
+
+Options:
+-i                Case-insensitive search
+--ascii-case      With -i, only fold the case of ASCII letters, which is faster on large ASCII files
+                  but means accented letters such as É no longer match é, with -E classes like \\w
+                  become ASCII only and patterns that could match non ASCII bytes, such as ., are rejected
+--no-unicode      Turn off Unicode in the pattern, \\w, \\d and -i then only know ASCII and patterns
+                  using \\p{L} or that could match non ASCII bytes, such as ., fail to compile, big
+                  patterns like \\w{50} compile many times smaller but searching a 100 MB ASCII file
+                  was only about 5% faster as ASCII text is already searched quickly
+-E                Treat the pattern as a regular expression instead of literal text
+--literal-prefix <text>
+                  With -E, match text literally right before the pattern, so in --literal-prefix
+                  'config.json' -E ':[0-9]+' the . needs no escaping, with -e it comes before each pattern
+-e <pattern>      Search for pattern, can be given several times to find lines matching any of them,
+                  no other pattern is given with -e, a --replace applies to the matches of every pattern
+                  and with -E capture groups are numbered across all of the patterns in order
+--per-pattern-stats
+                  At the end print how many lines and files each pattern matched
+-w                Only match whole words, a match can not have a word character right before or after it
+--word-chars <set>
+                  With -w, use the characters in the regex class set as word characters instead of
+                  letters, digits and underscores, e.g. 'a-zA-Z' makes foo a word in foo_bar
+-n                Print line numbers
+-v                Invert match (exclude lines that match the pattern)
+-r                Recursive directory search, directories are read and their files searched on several
+                  threads at once, the output of each file is held back until the files found before it
+                  are printed so the files come out whole in the order the walk found them, which can
+                  change from run to run, with --head, -A, -B, -C, --unique-lines and other options
+                  that carry over from one file to the next the files are searched one at a time as
+                  they are found
+--git-root        Recursively search the whole git repository the current directory is in, no file
+                  paths are given with --git-root
+--git-diff        Only match lines added or changed in tracked files of the working tree since HEAD,
+                  other lines are still printed as context, outside of a git repository every line is searched
+--exclude-dir <glob>
+                  Skip directories whose name matches glob when searching recursively, can be repeated
+-f                Print filenames even when searching a single file, filenames are printed by default
+                  when more than one file is searched or with -r
+--no-filename     Never print filenames
+--path-display=<relative|absolute>
+                  Print filenames relative to the current directory or as fully resolved absolute paths
+                  instead of as they were found
+-c                Enable colored output
+--color=<when>    Color output always, never or auto (only when printing to a terminal)
+--highlight-line  With colored output, also give the whole matching line a background color
+--color-groups    With -E and colored output, color each capture group of a match in matching lines
+                  differently (group 1 green, 2 blue, 3 magenta, 4 cyan, 5 yellow, then green again)
+                  and the rest of the match red, to see which part of a complex regex matched what
+--show-whitespace Show spaces in matched text as · and tabs as →, to find trailing whitespace with
+                  patterns like -E ' +$', also --highlight-whitespace
+--hyperlink       With colored output to a terminal, make filenames clickable links to the matched line
+-m <n>            Stop searching a file after n matching lines
+-A <n>            Print n lines of context after each matching line
+-B <n>            Print n lines of context before each matching line
+-C <n>            Print n lines of context before and after each matching line, blocks of context
+                  that overlap or touch are merged and other blocks are separated by --, also the
+                  blocks of different files, every context line is prefixed with its own filename
+--companion-offset <n>
+                  With every matching line also print the line n lines away from it (n can be
+                  negative), companions that would be outside of the file are skipped
+--max-matches-total <n>
+                  Stop the whole search after n matches across all files
+--head <n>        Stop the whole search once n lines have been printed, so with -o after n matches
+--tail <n>        Only print the last n lines of the output once every file has been searched, with -o
+                  the last n matches, only those n lines are held in memory
+-o                Print only the matched parts of matching lines, each on its own line
+--normalize <form>
+                  With --replace, put the text of each match and capture group into the Unicode
+                  normalization form NFC, NFD, NFKC or NFKD, e.g. --replace '$0' --normalize NFC joins
+                  a decomposed e and combining accent into é, this can change the length of a line in
+                  bytes so files edited with --in-place may grow or shrink
+--json-escape     With -o or --replace, escape quotes, backslashes and control characters in the matched
+                  text so it can be put inside a JSON string, with --replace only the match and the
+                  capture groups are escaped, e.g. --replace '{\"name\": \"$1\"}' --json-escape
+--max-per-line <n>
+                  With -o, print at most n matches from each line, -m still limits the number of
+                  matching lines first so at most m * n matches are printed per file
+--max-line-length <n>
+                  Skip lines longer than n characters (bytes with --bytes) without matching them,
+                  for files with huge minified lines, --verbose prints every line that was skipped,
+                  skipped lines are not printed with -v either but can still be shown as context
+--count           Only print the number of matching lines in each file that had a match, with -o the
+                  number of matches is counted instead, so a line with 3 matches counts as 3
+--rank            Print the number of matching lines followed by the file for every file that had a
+                  match, files with the most matches first and ties sorted by path
+--list-sorted     Only print the files that had a match, sorted and each printed once even when it was
+                  given more than once
+--unique-lines    Print each distinct matching line only once in the whole run, the first time it is
+                  found, later copies in any file are still counted as matches but not printed, every
+                  distinct line is kept in memory until the end of the run (see --max-memory)
+--group-by <n>    With -E, count how often each value of capture group n was matched across all files
+                  and print every value with its count, most often first, instead of the matching
+                  lines, matches where group n did not take part are not counted
+--time-group <unit>
+                  Instead of the matching lines, print a histogram of how many matching lines fell in
+                  each minute, hour or day, in time order, from the first timestamp like 2024-05-01
+                  13:45 or 2024-05-01T13:45:10 in each line, lines without one are not counted
+--time-regex <pattern>
+                  With --time-group, take the timestamp from the first capture group of the regex
+                  pattern, or its whole match when it has none, instead of from anywhere in the line
+--count-files     Print every file that had a match followed by its number of matching lines, even when
+                  only one file is searched, files without a match are never printed
+--with-zero       With --count or --rank, also print files that had no matches as 0
+--min-count <n>   With --count, --rank or --count-files, only print files with at least n matches,
+                  --count-total still adds up the matches of every file
+--count-total     With --count or --rank and more than one file, finish with a total: n line
+--count-format <style>
+                  How --count, --rank and --count-files print each file: plain (the default),
+                  kv (path=<path> count=n, paths with spaces, quotes or = are quoted) or json (one
+                  {\"path\":...,\"count\":n} object per line), so paths holding colons are read back safely
+--overlapping     Also find matches that overlap earlier ones when printing -o matches and highlighting,
+                  replacements never overlap so --replace is not affected
+--index <path>    Experimental, keep a trigram index of the searched files in path and skip files that can
+                  not contain the pattern, it is made by the first run and files changed since they were
+                  indexed are read again, only literal patterns without -v or --with-zero skip files
+--clipboard       Search the text in the system clipboard instead of files, it is labeled like standard
+                  input and no file paths can be given, fails on systems without a clipboard
+--files-from <path>
+                  Also search the files listed in path, one per line (- reads the list from stdin),
+                  a listed file that is missing is reported and makes the run exit with 2
+--replace <text>  Print matching lines with every match replaced by text ($0 is the match, with -E
+                  $1 or ${name} refer to capture groups), \\n, \\t, \\r and \\\\ in text are turned into
+                  a newline, tab, carriage return and backslash and can be mixed with group references,
+                  \\U and \\L upper and lower case the rest of the replacement until \\E, e.g. '\\U$1',
+                  {file} and {line} are the path and number of the line being replaced, e.g.
+                  --replace '{file}:{line} $0' for a cross reference listing, write \\{ for a literal {
+--prepend <text>  Print matching lines with text inserted right before every match, the match itself
+                  is kept, \\n, \\t, \\r and \\\\ work as in --replace, with --in-place the files are changed
+--append <text>   Like --prepend but inserts text right after every match, both can be given together
+                  to wrap matches, e.g. --prepend '[' --append ']'
+--replace-max-per-line <n>
+                  With --replace, only replace the first n matches on each line and leave the rest as they are
+--keep-indent     With --replace, keep the spaces and tabs a matching line started with even when the
+                  match covered them or the replacement changed them
+--if-match <pattern>
+                  With --replace, only replace on matching lines that also contain pattern (a regex
+                  with -E), other matching lines are printed unchanged
+--sub <find>=<replace>
+                  Replace every match of the regex find with replace, can be given several times
+                  and the rules are applied in order to each line, no pattern is given with --sub,
+                  write \\= for an = that is part of find
+--delete-matches  Print the matching lines that would be deleted, with --in-place delete them from the files
+--in-place        With --replace, --sub or --delete-matches, write the changed files back instead of printing
+                  the changed lines, lines that are kept are written back byte for byte, a file is
+                  skipped and reported when a line to be changed is not valid UTF-8, changed files are
+                  held in memory until every file has been searched, then N files, M replacements is
+                  printed to stderr before any of them is written
+--max-changes <n> With --in-place, abort without changing any file if more than n changes would be made
+--transaction     With --in-place, only change files once every file has been searched and all of the
+                  changed files could be written, otherwise no file is changed at all
+--confirm         With --in-place, ask on stderr before applying each change (y/n), changes are
+                  never applied when standard input is not a terminal
+--confirm-summary With --in-place, after N files, M replacements is printed ask once (y/n) before changing
+                  any of the files, answering n or not having a terminal to answer from exits with 1 and
+                  leaves every file as it was
+--patch-out <path>
+                  With --in-place, also write a unified diff of every change made to path, so the edit
+                  can be reviewed or undone with patch -p0 -R < path
+--preview <n>     With --replace, --sub or --delete-matches, only show the first n changes and never modify files
+--check           With --replace, --sub or --delete-matches, print the changes that would be made without
+                  modifying files, then exit with 1 if there were any and 0 if there were none, for CI
+--strict-utf8     Fail on invalid UTF-8 instead of replacing it, reporting where decoding failed
+--keep-newline    Match against each line including its line terminator so -E patterns can use \\n,
+                  $ then only matches after the newline so use (?m)$ to anchor before it
+-U, --multiline   Match the pattern against each whole file so -E patterns can use \\n to match across
+                  lines, matches are printed with every line they cover and -n gives the line the
+                  match starts on, --count counts matches instead of lines, each file is read into memory,
+                  with --replace every match in the whole file is replaced at once and . also matches
+                  newlines, so multi-line blocks can be collapsed, e.g. -U -E --replace '' 'BEGIN.*?END\\n',
+                  the rewritten file is printed or with --in-place written back, this holds both the
+                  old and new contents of each file in memory, so about twice its size (see --max-memory),
+                  -m, --max-matches-total and --preview can not limit it to some of the changes
+--column          With --multiline, also print the column the match starts at (-n is implied), a
+                  match covering several lines is given as start-end lines, e.g. a.txt: 3-5: 7: ...
+--encoding-fallback <name>
+                  Decode files that are not valid UTF-8 with the encoding name (e.g. windows-1252 or
+                  shift_jis) instead, this reads each file into memory before searching it
+--verbose         Print extra details about the search to stderr, like which files used --encoding-fallback
+                  and which files had no changes made to them
+--errors-to-stdout
+                  Print error messages to stdout instead of stderr, for CI systems that only capture stdout
+-S, --no-messages Do not print errors about files or directories that can not be read, the other files are
+                  still searched and the run still exits with 2
+--strict          Stop the whole run with exit code 2 at the first error of any kind, like a file that
+                  can not be read or invalid UTF-8 with --strict-utf8, instead of searching the other
+                  files, for CI jobs that should fail on any error, can not be used with -S
+--silent-scan     Search every file but print no matches, only the exit code tells if anything matched
+                  (0) or not (1), unlike grep -q it does not stop at the first match so every file is
+                  read, e.g. for health checks, files that can not be read are still reported unless -S
+--print0-lines    End every output record, the filename, line number and line together, with a NUL byte
+                  instead of a newline, so tools like xargs -0 can split them even when a --multiline
+                  match covers several lines
+--sort-output[=reverse]
+                  Print output lines sorted (or reverse sorted), this buffers all output in memory
+                  until every file has been searched
+--bytes           Match raw bytes instead of UTF-8 text (with -E, \\xNN matches a single byte), bytes
+                  that are not printable ASCII are printed as \\xNN, -m, -o, --max-per-line, -A, -B
+                  and -C work as they do on text
+-z, --search-compressed
+                  Search inside gzip, bzip2 and xz compressed files, detected by their magic bytes
+--csv             Print one CSV row per match with the columns path, line_number, column, match and line
+--field <n>       Print only field n of each matching line (counting from 1) after the filename and line
+                  number, instead of the whole line, lines with fewer fields print an empty field
+--delimiter <char>
+                  With --field, split lines into fields on char instead of a tab, \\t also means a tab
+--stats           Print a summary of the run, including the share of searched files that matched and
+                  how many lines and bytes were read
+--summary-only    Print only the --stats summary and none of the matches, the matches are still counted
+                  the same way, so with -v the summary counts the lines that do not match
+--json-pretty     Print all matches as one pretty printed JSON document grouped by file, everything is
+                  buffered in memory until the end of the run so only use it for small result sets
+--json            Print a stream of JSON objects, one per line, each with a type of begin (before the
+                  first match in a file), match, end (after the last match in a file, with its number
+                  of matching lines) or summary (the totals of the run, printed last)
+--max-memory <size>
+                  Stop with an error instead of running out of memory when the output held back by
+                  --sort-output, --json-pretty, --unique-lines, --in-place or -r, or a file read whole by
+                  --encoding-fallback, would need more than size bytes (K, M and G suffixes work)
+--regex-size-limit <size>
+                  Limit how much memory the compiled pattern may use, and separately how much its
+                  matching cache may use, to size bytes (K, M and G suffixes work), raise it for
+                  big patterns that are rejected as too large, the default is the regex crate's own
+--timings         Print how long each file took to search to stderr, slowest first
+--pager           When printing to a terminal, show the output in $PAGER (less -R if it is not set)
+--watch           Search again every time one of the files, or a file in one of the directories, changes,
+                  clearing the screen first when printing to a terminal, stop it with Ctrl-C, a list of
+                  files to watch is given with --files-from <path> as it can not be read from stdin again
+--wrap            When printing to a terminal, wrap long lines to its width and indent the continued
+                  parts so they line up after the filename and line number
+--compare         When exactly two files are searched, print their matching lines side by side in two
+                  columns that each take half of the terminal width, the first file on the left, lines
+                  too long for a column are cut short, with any other number of files it is ignored
+-h, --help        Show help information";
+    // help shown after a mistake belongs with the error so it stays out of piped output,
+    // only help asked for with -h goes to stdout
+    if on_error {
+        print_error(help);
+    } else {
+        print_line(help);
+    }
+}
+
+/*
+Breif Explanation: Searches a given file in the way the options ask for, raw bytes, whole files or line by line.
+
+Parameters: 
+    file_path: &String - the file path for a given file.
+    config: &Config - instance of a config struct that holds search options.    
+    re: &Regex - the regex pattern, compiled once for the whole run.
+    byte_re: Option<&regex::bytes::Regex> - the regex pattern for --bytes, None without it.
+    totals: &mut RunTotals - counts accumulated across all files, updated as the file is searched.
+    output: &mut Output - where matched lines are printed to.
+    pending_writes: &mut Vec<(String, Vec<u8>)> - edited files held back to be written at the end of the run.
+
+Returns: 
+    Ok(()) - search done.
+    Err(String) - Error mesage if searching a file fails.
+*/
+fn search_one_file(
+    file_path: &String,
+    config: &Config,
+    re: &Regex,
+    byte_re: Option<&regex::bytes::Regex>,
+    totals: &mut RunTotals,
+    output: &mut Output,
+    pending_writes: &mut Vec<(String, Vec<u8>)>,
+) -> Result<(), String> {
+    match byte_re {
+        Some(byte_re) => search_file_bytes(file_path, config, byte_re, totals, output),
+        None if config.multiline => {
+            search_file_multiline(file_path, config, re, totals, output, pending_writes)
+        }
+        None => search_file(file_path, config, re, totals, output, pending_writes),
+    }
+}
+
+/*
+Breif Explanation: Searches a file found by -r on the walker thread that found it, holding on to everything the search produced.
+
+Parameters: 
+    file_path: &String - the file path for a given file.
+    config: &Config - instance of a config struct that holds search options.    
+    re: &Regex - the regex pattern, compiled once for the whole run.
+    byte_re: Option<&regex::bytes::Regex> - the regex pattern for --bytes, None without it.
+
+Returns: 
+    FileSearch - the lines printed, counts and edits of the file, added to the run by add_file_search.
+*/
+fn search_on_walker_thread(
+    file_path: &String,
+    config: &Config,
+    re: &Regex,
+    byte_re: Option<&regex::bytes::Regex>,
+) -> FileSearch {
+    let mut output = Output {
+        file_records: Some(Vec::new()),
+        ..Output::new(config)
+    };
+    let mut totals = RunTotals::new(config);
+    let mut pending_writes = Vec::new();
+    let search_start = Instant::now();
+    let result = search_one_file(
+        file_path,
+        config,
+        re,
+        byte_re,
+        &mut totals,
+        &mut output,
+        &mut pending_writes,
+    );
+    output.end_json_file(totals.matches);
+    FileSearch {
+        output,
+        totals,
+        pending_writes,
+        elapsed: search_start.elapsed(),
+        result,
+    }
+}
+
+/*
+Breif Explanation: Adds a file searched on a walker thread to the run, as if it had just been searched.
+
+Parameters: 
+    file_search: FileSearch - everything searching the file produced.
+    totals: &mut RunTotals - counts accumulated across all files.
+    output: &mut Output - where the lines printed by the search are printed to.
+    pending_writes: &mut Vec<(String, Vec<u8>)> - edited files held back to be written at the end of the run.
+
+Returns: 
+    Ok(()) - the file was searched.
+    Err(String) - Error mesage if searching the file failed.
+*/
+fn add_file_search(
+    file_search: FileSearch,
+    totals: &mut RunTotals,
+    output: &mut Output,
+    pending_writes: &mut Vec<(String, Vec<u8>)>,
+) -> Result<(), String> {
+    let mut file_output = file_search.output;
+    for record in file_output.file_records.take().unwrap_or_default() {
+        output.emit(record);
+    }
+    for file_result in file_output.json_results.take().unwrap_or_default() {
+        for json_match in file_result.matches {
+            output.add_json_match(&file_result.path, json_match);
+        }
+    }
+    if !file_output.patch.is_empty() {
+        output.track_buffered(file_output.patch.len(), "--patch-out");
+        output.patch.push_str(&file_output.patch);
+    }
+    for (file_path, new_content) in file_search.pending_writes {
+        output.track_buffered(new_content.len(), "--in-place");
+        pending_writes.push((file_path, new_content));
+    }
+    totals.add(file_search.totals);
+    file_search.result
+}
+
+/*
+Breif Explanation: Searches for pattern in a given file.
+
+Parameters: 
+    file_path: &String - the file path for a given file.
+    config: &Config - instance of a config struct that holds search options.    
+    re: &Regex - the regex pattern, compiled once for the whole run.
+    totals: &mut RunTotals - counts accumulated across all files, updated as the file is searched.
+    output: &mut Output - where matched lines are printed to.
+    pending_writes: &mut Vec<(String, Vec<u8>)> - edited files held back to be written at the end of the run.
+
+Returns: 
+    Ok(()) - search done.
+    Err(String) - Error mesage if searching a file fails.
+*/
+fn search_file(
+    file_path: &String,
+    config: &Config,
+    re: &Regex,
+    totals: &mut RunTotals,
+    output: &mut Output,
+    pending_writes: &mut Vec<(String, Vec<u8>)>,
+) -> Result<(), String> {
+    //buffer used to read a single line from the file at a time
+    let mut buf_reader = open_for_search(file_path, config)?;
+    if let Some(encoding) = config.encoding_fallback {
+        buf_reader = decode_with_fallback(file_path, config, encoding, buf_reader, output)?;
+    }
+    // when editing in place the whole file is rebuilt here and written back once every line has been read
+    let mut new_content: Vec<u8> = Vec::new();
+    // the lines before and after each change, only kept for --patch-out
+    let mut line_changes: Vec<LineChange> = Vec::new();
+    let mut file_replacements = 0;
+    let mut file_matches = 0;
+    // the first changed line that was not valid UTF-8, writing it back would swap its invalid bytes for U+FFFD
+    let mut corrupted_line = None;
+    //go line by line and if no error is hit then prin the line and the associated data based on the config instance
+    let mut line_bytes = Vec::new();
+    let mut i = 0;
+    // byte offset of the start of the current line within the file, used to locate invalid UTF-8
+    let mut line_offset = 0;
+    // if the last line read had a newline, in place edits keep the file ending the same way
+    let mut ends_with_newline = true;
+    // lines kept for -B, the last line printed and how many -A lines are still owed, so that
+    // overlapping context windows are merged into one block instead of printing lines twice,
+    // they start over for every file so context never carries over into the next file
+    let mut before_lines: VecDeque<(usize, String)> = VecDeque::new();
+    let mut last_printed = None;
+    let mut after_remaining = 0;
+    // lines a negative --companion-offset can still reach back to, and the line numbers a positive one is waiting for
+    let mut recent_lines: VecDeque<(usize, String)> = VecDeque::new();
+    let mut pending_companions: VecDeque<usize> = VecDeque::new();
+    // with --git-diff only the lines added since HEAD can match, a file git did not report has none
+    let changed_lines = config.git_diff_lines.as_ref().map(|git_diff_lines| {
+        fs::canonicalize(file_path)
+            .ok()
+            .and_then(|path| git_diff_lines.get(&path))
+    });
+    // which of the --per-pattern-stats patterns matched somewhere in this file
+    let mut file_patterns = vec![false; config.pattern_stats.len()];
+    let companion_offset = match config.companion_offset {
+        Some(offset) if config.prints_whole_lines() => offset,
+        _ => 0,
+    };
+    loop {
+        line_bytes.clear();
+        match buf_reader.read_until(b'\n', &mut line_bytes) {
+            Ok(0) => break,
+            Ok(_) => (),
+            Err(_) => return Err(format!("Could not read line {} from {}", i + 1, file_path)),
+        }
+        i += 1;
+        // every line read counts towards the cost of the search shown by --stats, matching or not
+        totals.lines_scanned += 1;
+        totals.bytes_scanned += line_bytes.len();
+        // the line terminator is kept aside so in place edits write back the exact same line endings,
+        // with --keep-newline it stays part of the line that is matched
+        let (content, terminator) = if config.keep_newline {
+            (line_bytes.as_slice(), &b""[..])
+        } else {
+            split_line_terminator(&line_bytes)
+        };
+        //invalid UTF-8 is replaced with U+FFFD unless --strict-utf8 asks for it to be reported
+        let mut decoded_lossily = false;
+        let line = match std::str::from_utf8(content) {
+            Ok(line) => line.to_string(),
+            Err(e) if config.strict_utf8 => {
+                return Err(format!(
+                    "Invalid UTF-8 in {} on line {} at byte {} (byte offset {} in the file)",
+                    file_path,
+                    i,
+                    e.valid_up_to() + 1,
+                    line_offset + e.valid_up_to()
+                ))
+            }
+            Err(_) => {
+                decoded_lossily = true;
+                String::from_utf8_lossy(content).to_string()
+            }
+        };
+        line_offset += line_bytes.len();
+        // lines that are not replaced are written back byte for byte
+        let mut output_line = None;
+        // a line over --max-line-length never reaches the regex, it is neither a match nor a line -v prints
+        let too_long = line_too_long(config, line.len(), || line.chars().count());
+        if too_long && config.verbose {
+            eprintln!(
+                "{}: {}: skipped line longer than --max-line-length",
+                file_path, i
+            );
+        }
+        // with --sub a line matches when any of the rules changed it
+        let substituted = if config.subs.is_empty() || too_long {
+            None
+        } else {
+            Some(apply_substitutions(&config.subs, &line))
+        };
+        let (pattern_found, display_line) = match &substituted {
+            _ if too_long => (false, String::new()),
+            Some((_, rule_counts)) => (rule_counts.iter().any(|&count| count > 0), line.clone()),
+            None => pattern_in_line(re, config, &line),
+        };
+        // once the global or per file match cap has been hit stop reading, unless the rest of the file still has to be written back
+        let cap_reached = config
+            .max_total
+            .is_some_and(|max_total| totals.matches >= max_total)
+            || config
+                .max_count
+                .is_some_and(|max_count| file_matches >= max_count);
+        // the context after the last match is still printed once the cap has been hit
+        if cap_reached && !config.in_place && after_remaining == 0 && pending_companions.is_empty()
+        {
+            break;
+        }
+        if config.preview_reached(totals.replacements + file_replacements) || output.head_reached()
+        {
+            break;
+        }
+        while pending_companions.front() == Some(&i) {
+            pending_companions.pop_front();
+            print_companion_line(config, output, file_path, i, &line);
+        }
+        // with --delete-matches a matching line is left out of the rewritten file entirely
+        let mut delete_line = false;
+        let on_changed_line = match changed_lines {
+            Some(Some(lines)) => lines.contains(&i),
+            Some(None) => false,
+            None => true,
+        };
+        if should_print(config.invert_match, pattern_found)
+            && on_changed_line
+            && !cap_reached
+            && !too_long
+        {
+            if config.uses_context() {
+                for (line_number, context_line) in before_lines.drain(..) {
+                    print_context_line(
+                        config,
+                        output,
+                        file_path,
+                        line_number,
+                        &context_line,
+                        &mut last_printed,
+                    );
+                }
+                start_context_group(output, last_printed, i);
+                last_printed = Some(i);
+                after_remaining = config.after_context;
+            }
+            // how much the line adds to the match count, every match on it for -o with --count
+            let mut occurrences = 1;
+            // with --confirm a change that is turned down leaves the line as it was
+            if config.delete_matches {
+                if change_confirmed(config, file_path, i, &line, None) {
+                    file_replacements += 1;
+                    if !config.in_place {
+                        print_match(config, output, file_path, i, &display_line);
+                    }
+                    delete_line = true;
+                }
+            } else if let Some(replacement) = &config.replace {
+                // with --if-match lines that do not also match the condition are left as they are
+                let condition_met = match &config.if_match {
+                    Some(condition) => condition.is_match(&line),
+                    None => true,
+                };
+                let (mut replaced_line, replacements) = if !condition_met {
+                    (line.clone(), 0)
+                } else if config.replace_tokens {
+                    // the tokens are filled in first so capture groups are still expanded by the regex
+                    let shown_path = display_path(config.path_display, file_path);
+                    let replace_case: Vec<CaseSegment> = config
+                        .replace_case
+                        .iter()
+                        .map(|segment| CaseSegment {
+                            case: segment.case,
+                            template: fill_replacement_tokens(&segment.template, &shown_path, i),
+                        })
+                        .collect();
+                    replace_in_line(
+                        re,
+                        &fill_replacement_tokens(replacement, &shown_path, i),
+                        &replace_case,
+                        &line,
+                        config,
+                    )
+                } else {
+                    replace_in_line(re, replacement, &config.replace_case, &line, config)
+                };
+                if config.keep_indent && replacements > 0 {
+                    replaced_line = keep_indent(&line, &replaced_line);
+                }
+                if replacements > 0
+                    && change_confirmed(config, file_path, i, &line, Some(&replaced_line))
+                {
+                    file_replacements += replacements;
+                    output_line = Some(replaced_line.clone());
+                }
+                if !config.in_place {
+                    print_match(config, output, file_path, i, &replaced_line);
+                }
+            } else if let Some((substituted_line, rule_counts)) = substituted {
+                let substitutions: usize = rule_counts.iter().sum();
+                if substitutions > 0
+                    && change_confirmed(config, file_path, i, &line, Some(&substituted_line))
+                {
+                    file_replacements += substitutions;
+                    for (total, count) in totals.sub_counts.iter_mut().zip(rule_counts) {
+                        *total += count;
+                    }
+                    output_line = Some(substituted_line.clone());
+                }
+                if !config.in_place {
+                    print_match(config, output, file_path, i, &substituted_line);
+                }
+            } else if config.count {
+                //only the per file total is printed once the file has been searched,
+                //with -o it is the number of matches -o would print rather than lines
+                if config.only_matching && !config.invert_match {
+                    let max_per_line = config.max_per_line.unwrap_or(usize::MAX);
+                    occurrences = find_matches(re, &line, config).len().min(max_per_line);
+                }
+                if let Some(group) = config.group_by {
+                    count_group_values(re, config, group, &line, &mut totals.group_counts);
+                }
+                if let Some(time_unit) = config.time_group {
+                    count_time_bucket(config, time_unit, &line, &mut totals.time_buckets);
+                }
+            } else if config.csv {
+                print_csv_rows(config, output, re, file_path, i, &line)?;
+            } else if config.json_pretty {
+                output.add_json_match(file_path, json_match(re, config, i, &line));
+            } else if config.json {
+                output.add_json_event(file_path, json_match(re, config, i, &line));
+            } else if config.only_matching {
+                print_only_matching(config, output, re, file_path, i, &line);
+            } else if output.first_sighting(&line) {
+                print_match(config, output, file_path, i, &display_line);
+            }
+            totals.matches += occurrences;
+            file_matches += 1;
+            for (index, pattern_re) in config.pattern_stats.iter().enumerate() {
+                if pattern_re.is_match(&line) {
+                    totals.pattern_lines[index] += 1;
+                    file_patterns[index] = true;
+                }
+            }
+            // a companion line outside of the file is skipped
+            if companion_offset > 0 {
+                pending_companions.push_back(i + companion_offset as usize);
+            } else if companion_offset < 0 && recent_lines.len() == companion_offset.unsigned_abs()
+            {
+                if let Some((companion_number, companion_line)) = recent_lines.front() {
+                    print_companion_line(
+                        config,
+                        output,
+                        file_path,
+                        *companion_number,
+                        companion_line,
+                    );
+                }
+            }
+        } else if config.uses_context() {
+            if after_remaining > 0 {
+                print_context_line(config, output, file_path, i, &line, &mut last_printed);
+                after_remaining -= 1;
+            } else if config.before_context > 0 {
+                if before_lines.len() == config.before_context {
+                    before_lines.pop_front();
+                }
+                before_lines.push_back((i, line.clone()));
+            }
+        }
+        if companion_offset < 0 {
+            if recent_lines.len() == companion_offset.unsigned_abs() {
+                recent_lines.pop_front();
+            }
+            recent_lines.push_back((i, line.clone()));
+        }
+        ends_with_newline = line_bytes.ends_with(b"\n");
+        // the line terminators are part of the diff so patch can tell CRLF lines and a missing last newline apart
+        if config.patch_out.is_some() && (delete_line || output_line.is_some()) {
+            let terminator = String::from_utf8_lossy(terminator);
+            line_changes.push(LineChange {
+                line_number: i,
+                old_line: format!("{}{}", line, terminator),
+                new_line: output_line
+                    .as_ref()
+                    .map(|replaced_line| format!("{}{}", replaced_line, terminator)),
+            });
+        }
+        if decoded_lossily && output_line.is_some() && corrupted_line.is_none() {
+            corrupted_line = Some(i);
+        }
+        if config.in_place && !delete_line {
+            match &output_line {
+                Some(replaced_line) => new_content.extend_from_slice(replaced_line.as_bytes()),
+                None => new_content.extend_from_slice(content),
+            }
+            new_content.extend_from_slice(terminator);
+        }
+    }
+    for (pattern_files, matched) in totals.pattern_files.iter_mut().zip(file_patterns) {
+        if matched {
+            *pattern_files += 1;
+        }
+    }
+    // the whole file is skipped rather than writing back a part of the changes
+    if let Some(line_number) = corrupted_line.filter(|_| config.in_place) {
+        return Err(format!(
+            "Could not edit file in place without corrupting it: {} line {} is not valid UTF-8, the file was left unchanged",
+            file_path, line_number
+        ));
+    }
+    if config.changes_lines() {
+        totals.replacements += file_replacements;
+    }
+    // files without any change are never rewritten so their modification time is kept
+    if config.in_place && file_replacements > 0 {
+        // when the old last line is deleted the new last line would bring its newline along,
+        // so drop it again for files that did not end with a newline
+        if !ends_with_newline {
+            let content_length = split_line_terminator(&new_content).0.len();
+            new_content.truncate(content_length);
+        }
+        if config.patch_out.is_some() {
+            output.add_patch(file_path, &line_changes);
+        }
+        // nothing is written until every file has been searched and the size of the whole batch was printed
+        output.track_buffered(new_content.len(), "--in-place");
+        pending_writes.push((file_path.clone(), new_content));
+    }
+    Ok(())
+}
+
+/*
+Breif Explanation: Builds a unified diff without context lines for the changes made to one file, used for --patch-out.
+
+Parameters: 
+    file_path: &String - the file path for the edited file, used as both the old and new file name.
+    changes: &[LineChange] - every changed line of the file in order.
+
+Returns: 
+    String - the diff, with one hunk for each run of changes on consecutive lines.
+*/
+fn unified_diff(file_path: &String, changes: &[LineChange]) -> String {
+    let mut diff = format!("--- {}\n+++ {}\n", file_path, file_path);
+    // how many lines the changes so far have added to or removed from the file
+    let mut offset: isize = 0;
+    let mut hunk_start = 0;
+    while hunk_start < changes.len() {
+        let mut hunk_end = hunk_start + 1;
+        while hunk_end < changes.len()
+            && changes[hunk_end].line_number == changes[hunk_end - 1].line_number + 1
+        {
+            hunk_end += 1;
+        }
+        let hunk = &changes[hunk_start..hunk_end];
+        // a replacement can contain newlines so the new side is split into lines again
+        let removed: Vec<&str> = hunk
+            .iter()
+            .flat_map(|change| change.old_line.split_inclusive('\n'))
+            .collect();
+        let added: Vec<&str> = hunk
+            .iter()
+            .filter_map(|change| change.new_line.as_deref())
+            .flat_map(|new_line| new_line.split_inclusive('\n'))
+            .collect();
+        let old_start = hunk[0].line_number as isize;
+        //like diff, a hunk that only removes lines starts on the new line just before them
+        let new_start = if added.is_empty() {
+            old_start + offset - 1
+        } else {
+            old_start + offset
+        };
+        diff.push_str(&format!(
+            "@@ -{},{} +{},{} @@\n",
+            old_start,
+            removed.len(),
+            new_start,
+            added.len()
+        ));
+        let removed_lines = removed.iter().map(|line| ('-', line));
+        let added_lines = added.iter().map(|line| ('+', line));
+        for (sign, line) in removed_lines.chain(added_lines) {
+            diff.push(sign);
+            diff.push_str(line);
+            if !line.ends_with('\n') {
+                diff.push_str("\n\\ No newline at end of file\n");
+            }
+        }
+        offset += added.len() as isize - removed.len() as isize;
+        hunk_start = hunk_end;
+    }
+    diff
+}
+
+/*
+Breif Explanation: Reads a whole file for --encoding-fallback and decodes it with the fallback encoding if it is not valid UTF-8.
+
+Parameters: 
+    file_path: &String - the file path for a given file.
+    config: &Config - instance of a config struct that holds search options.    
+    encoding: &'static Encoding - the encoding used when the file is not valid UTF-8.
+    buf_reader: Box<dyn BufRead> - the opened file.
+    output: &Output - checks the size of the file against --max-memory.
+
+Returns: 
+    Ok(Box<dyn BufRead>) - buffered reader over the contents of the file as UTF-8.
+    Err(String) - Error mesage if the file could not be read or a decoded file would be edited in place.
+*/
+fn decode_with_fallback(
+    file_path: &String,
+    config: &Config,
+    encoding: &'static Encoding,
+    mut buf_reader: Box<dyn BufRead>,
+    output: &Output,
+) -> Result<Box<dyn BufRead>, String> {
+    let mut contents = Vec::new();
+    if buf_reader.read_to_end(&mut contents).is_err() {
+        return Err(format!("Could not read file: {}", file_path));
+    }
+    output.check_memory(contents.len(), "--encoding-fallback");
+    if std::str::from_utf8(&contents).is_ok() {
+        return Ok(Box::new(Cursor::new(contents)));
+    }
+    // writing the lines back would silently turn the file into UTF-8
+    if config.in_place {
+        return Err(format!(
+            "Could not edit file in place that is not valid UTF-8: {}",
+            file_path
+        ));
+    }
+    if config.verbose {
+        eprintln!("{}: decoded as {}", file_path, encoding.name());
+    }
+    let (decoded, _) = encoding.decode_without_bom_handling(&contents);
+    Ok(Box::new(Cursor::new(decoded.into_owned().into_bytes())))
+}
+
+//The SpecialFile Enum holds the kinds of files that are not regular files and need their own handling
+enum SpecialFile {
+    Device,
+    Stream,
+}
+
+/*
+Breif Explanation: Checks if a path is a device or a named pipe, on platforms without them nothing is special.
+
+Parameters: 
+    file_path: &str - the file path for a given file.
+
+Returns: 
+    Option<SpecialFile> - the kind of special file or None for regular files and paths that can not be read.
+*/
+#[cfg(unix)]
+fn special_file(file_path: &str) -> Option<SpecialFile> {
+    use std::os::unix::fs::FileTypeExt;
+    let file_type = match fs::metadata(file_path) {
+        Ok(metadata) => metadata.file_type(),
+        Err(_) => return None,
+    };
+    if file_type.is_char_device() || file_type.is_block_device() {
+        Some(SpecialFile::Device)
+    } else if file_type.is_fifo() || file_type.is_socket() {
+        Some(SpecialFile::Stream)
+    } else {
+        None
+    }
+}
+
+#[cfg(not(unix))]
+fn special_file(_file_path: &str) -> Option<SpecialFile> {
+    None
+}
+
+//The Compression Enum holds the compressed formats that --search-compressed can read
+enum Compression {
+    Gzip,
+    Bzip2,
+    Xz,
+}
+
+/*
+Breif Explanation: Opens a file for searching, decompressing it on the fly with --search-compressed.
+
+Parameters: 
+    file_path: &String - the file path for a given file.
+    config: &Config - instance of a config struct that holds search options.    
+
+Returns: 
+    Ok(Box<dyn BufRead>) - buffered reader over the (decompressed) contents of the file.
+    Err(String) - Error mesage if the file could not be opened.
+*/
+fn open_for_search(file_path: &String, config: &Config) -> Result<Box<dyn BufRead>, String> {
+    //a path of - reads from stdin, or the clipboard with --clipboard, neither can be written back to
+    let mut buf_reader: Box<dyn BufRead> = if file_path == "-" {
+        if config.in_place {
+            return Err(String::from("Could not edit standard input in place"));
+        }
+        match &config.clipboard {
+            Some(contents) => Box::new(Cursor::new(contents.clone().into_bytes())),
+            None => Box::new(BufReader::new(io::stdin())),
+        }
+    } else {
+        // devices like /dev/null are searched as if they were empty and named pipes are only ever read as a stream,
+        // neither can be replaced by an edited copy
+        let special_file = special_file(file_path);
+        if special_file.is_some() && config.in_place {
+            return Err(format!(
+                "Could not edit special file in place: {}",
+                file_path
+            ));
+        }
+        if let Some(SpecialFile::Device) = special_file {
+            return Ok(Box::new(io::empty()));
+        }
+        match File::open(file_path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(_) => return Err(format!("Could not open file: {}", file_path)),
+        }
+    };
+    if !config.search_compressed {
+        return Ok(buf_reader);
+    }
+    //peek at the start of the file so nothing has to be read twice
+    let compression = match buf_reader.fill_buf() {
+        Ok(start) => detect_compression(file_path, start),
+        Err(_) => return Err(format!("Could not read file: {}", file_path)),
+    };
+    if compression.is_some() && config.in_place {
+        return Err(format!(
+            "Could not edit compressed file in place: {}",
+            file_path
+        ));
+    }
+    //the multi stream decoders also read files made of several concatenated compressed streams
+    Ok(match compression {
+        Some(Compression::Gzip) => Box::new(BufReader::new(flate2::read::MultiGzDecoder::new(
+            buf_reader,
+        ))),
+        Some(Compression::Bzip2) => {
+            Box::new(BufReader::new(bzip2::read::MultiBzDecoder::new(buf_reader)))
+        }
+        Some(Compression::Xz) => Box::new(BufReader::new(xz2::read::XzDecoder::new_multi_decoder(
+            buf_reader,
+        ))),
+        None => buf_reader,
+    })
+}
+
+/*
+Breif Explanation: Works out which compressed format a file uses from its magic bytes or its extension.
+
+Parameters: 
+    file_path: &String - the file path for a given file.
+    start: &[u8] - the first bytes of the file.
+
+Returns: 
+    Option<Compression> - the detected format, None for files that are not compressed.
+*/
+fn detect_compression(file_path: &String, start: &[u8]) -> Option<Compression> {
+    if start.starts_with(&[0x1f, 0x8b]) {
+        return Some(Compression::Gzip);
+    }
+    if start.starts_with(b"BZh") {
+        return Some(Compression::Bzip2);
+    }
+    if start.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+        return Some(Compression::Xz);
+    }
+    // without magic bytes fall back to the extension, an empty file is just an empty file though
+    if start.is_empty() {
+        return None;
+    }
+    match Path::new(file_path).extension().and_then(|e| e.to_str()) {
+        Some("gz") => Some(Compression::Gzip),
+        Some("bz2") => Some(Compression::Bzip2),
+        Some("xz") => Some(Compression::Xz),
+        _ => None,
+    }
+}
+
+/*
+Breif Explanation: Searches for pattern in the raw bytes of a given file, used for --bytes.
+
+Parameters: 
+    file_path: &String - the file path for a given file.
+    config: &Config - instance of a config struct that holds search options.    
+    re: &regex::bytes::Regex - the byte regex pattern, compiled once for the whole run.
+    totals: &mut RunTotals - counts accumulated across all files, updated as the file is searched.
+    output: &mut Output - where matched lines are printed to.
+
+Returns: 
+    Ok(()) - search done.
+    Err(String) - Error mesage if searching a file fails.
+*/
+fn search_file_bytes(
+    file_path: &String,
+    config: &Config,
+    re: &regex::bytes::Regex,
+    totals: &mut RunTotals,
+    output: &mut Output,
+) -> Result<(), String> {
+    let mut buf_reader = open_for_search(file_path, config)?;
+    let mut line_bytes = Vec::new();
+    let mut i = 0;
+    let mut file_matches = 0;
+    // lines kept for -B, the last line printed and how many -A lines are still owed, merged into
+    // blocks the same way as in search_file
+    let mut before_lines: VecDeque<(usize, String)> = VecDeque::new();
+    let mut last_printed = None;
+    let mut after_remaining = 0;
+    //lines are still split on \n bytes but never decoded
+    loop {
+        line_bytes.clear();
+        match buf_reader.read_until(b'\n', &mut line_bytes) {
+            Ok(0) => break,
+            Ok(_) => (),
+            Err(_) => return Err(format!("Could not read line {} from {}", i + 1, file_path)),
+        }
+        i += 1;
+        // every line read counts towards the cost of the search shown by --stats, matching or not
+        totals.lines_scanned += 1;
+        totals.bytes_scanned += line_bytes.len();
+        let cap_reached = config
+            .max_total
+            .is_some_and(|max_total| totals.matches >= max_total)
+            || config
+                .max_count
+                .is_some_and(|max_count| file_matches >= max_count);
+        // the context after the last match is still printed once the cap has been hit
+        if (cap_reached && after_remaining == 0) || output.head_reached() {
+            break;
+        }
+        let content = if config.keep_newline {
+            &line_bytes
+        } else {
+            line_bytes.strip_suffix(b"\n").unwrap_or(&line_bytes)
+        };
+        // lines are never decoded here so their length is counted in bytes
+        let too_long = line_too_long(config, content.len(), || content.len());
+        if too_long && config.verbose {
+            eprintln!(
+                "{}: {}: skipped line longer than --max-line-length",
+                file_path, i
+            );
+        }
+        if !too_long && !cap_reached && should_print(config.invert_match, re.is_match(content)) {
+            if config.uses_context() {
+                for (line_number, context_line) in before_lines.drain(..) {
+                    print_context_line(
+                        config,
+                        output,
+                        file_path,
+                        line_number,
+                        &context_line,
+                        &mut last_printed,
+                    );
+                }
+                start_context_group(output, last_printed, i);
+                last_printed = Some(i);
+                after_remaining = config.after_context;
+            }
+            // like -o on text, -m limits the matching lines first and --max-per-line the matches of each
+            let max_per_line = config.max_per_line.unwrap_or(usize::MAX);
+            let mut occurrences = 1;
+            if config.only_matching && !config.invert_match {
+                let found = re.find_iter(content).take(max_per_line);
+                if config.count {
+                    occurrences = found.count();
+                } else {
+                    for found in found {
+                        let matched = escape_bytes(found.as_bytes());
+                        print_match(config, output, file_path, i, &matched);
+                    }
+                }
+            } else if !config.count {
+                print_match(config, output, file_path, i, &escape_bytes(content));
+            }
+            totals.matches += occurrences;
+            file_matches += 1;
+        } else if config.uses_context() {
+            if after_remaining > 0 {
+                let line = escape_bytes(content);
+                print_context_line(config, output, file_path, i, &line, &mut last_printed);
+                after_remaining -= 1;
+            } else if config.before_context > 0 {
+                if before_lines.len() == config.before_context {
+                    before_lines.pop_front();
+                }
+                before_lines.push_back((i, escape_bytes(content)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/*
+Breif Explanation: Checks if a line is longer than --max-line-length allows.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds the --max-line-length option.
+    byte_length: usize - the length of the line in bytes.
+    length: impl Fn() -> usize - gives the length of the line as it is measured, only called when it could be too long.
+
+Returns: 
+    bool - true if the line should be skipped.
+*/
+fn line_too_long(config: &Config, byte_length: usize, length: impl Fn() -> usize) -> bool {
+    // a line is never longer in characters than in bytes, so short lines are not counted at all
+    match config.max_line_length {
+        Some(max_line_length) => byte_length > max_line_length && length() > max_line_length,
+        None => false,
+    }
+}
+
+/*
+Breif Explanation: Searches for pattern in the whole of a given file at once so matches can span lines, used for --multiline.
+
+Parameters: 
+    file_path: &String - the file path for a given file.
+    config: &Config - instance of a config struct that holds search options.    
+    re: &Regex - the regex pattern, compiled once for the whole run.
+    totals: &mut RunTotals - counts accumulated across all files, updated as the file is searched.
+    output: &mut Output - where matches are printed to.
+    pending_writes: &mut Vec<(String, Vec<u8>)> - edited files held back to be written at the end of the run.
+
+Returns: 
+    Ok(()) - search done.
+    Err(String) - Error mesage if searching a file fails.
+*/
+fn search_file_multiline(
+    file_path: &String,
+    config: &Config,
+    re: &Regex,
+    totals: &mut RunTotals,
+    output: &mut Output,
+    pending_writes: &mut Vec<(String, Vec<u8>)>,
+) -> Result<(), String> {
+    let mut buf_reader = open_for_search(file_path, config)?;
+    let mut content_bytes = Vec::new();
+    if buf_reader.read_to_end(&mut content_bytes).is_err() {
+        return Err(format!("Could not read file: {}", file_path));
+    }
+    output.check_memory(content_bytes.len(), "--multiline");
+    let mut decoded_lossily = false;
+    let content = match String::from_utf8(content_bytes) {
+        Ok(content) => content,
+        Err(e) if config.strict_utf8 => {
+            return Err(format!(
+                "Invalid UTF-8 in {} at byte offset {}",
+                file_path,
+                e.utf8_error().valid_up_to()
+            ))
+        }
+        Err(e) => {
+            decoded_lossily = true;
+            String::from_utf8_lossy(e.as_bytes()).to_string()
+        }
+    };
+    totals.lines_scanned += content.lines().count();
+    totals.bytes_scanned += content.len();
+    // with --replace every match in the file is replaced in one go, so a replacement can join or split lines
+    if let Some(replacement) = &config.replace {
+        let (new_content, file_replacements) =
+            replace_in_line(re, replacement, &config.replace_case, &content, config);
+        // the original and the rewritten file are both held in memory at this point
+        output.check_memory(
+            content.len() + new_content.len(),
+            "--multiline with --replace",
+        );
+        totals.matches += file_replacements;
+        totals.replacements += file_replacements;
+        if file_replacements == 0 {
+            return Ok(());
+        }
+        if !config.in_place {
+            // the line numbers no longer line up with the file once lines are joined, so the new content is printed as is
+            for line in new_content.lines() {
+                if config.show_filenames() {
+                    let shown_path = display_path(config.path_display, file_path);
+                    output.emit(format!("{}: {}", shown_path, line));
+                } else {
+                    output.emit(line.to_string());
+                }
+            }
+            return Ok(());
+        }
+        // writing back the lossily decoded file would swap its invalid bytes for U+FFFD
+        if decoded_lossily {
+            return Err(format!(
+                "Could not edit file in place without corrupting it: {} is not valid UTF-8, the file was left unchanged",
+                file_path
+            ));
+        }
+        output.track_buffered(new_content.len(), "--in-place");
+        pending_writes.push((file_path.clone(), new_content.into_bytes()));
+        return Ok(());
+    }
+    // newlines are counted on from the previous match instead of from the start of the file each time
+    let mut line_number = 1;
+    let mut counted_to = 0;
+    for (file_matches, found) in find_matches(re, &content, config).into_iter().enumerate() {
+        if config
+            .max_count
+            .is_some_and(|max_count| file_matches >= max_count)
+            || config
+                .max_total
+                .is_some_and(|max_total| totals.matches >= max_total)
+        {
+            break;
+        }
+        line_number += content[counted_to..found.start()].matches('\n').count();
+        counted_to = found.start();
+        // a newline the match ends with belongs to the last line it covers
+        let matched = found.as_str();
+        let end_line = line_number
+            + matched
+                .strip_suffix('\n')
+                .unwrap_or(matched)
+                .matches('\n')
+                .count();
+        if !config.count {
+            print_multiline_match(
+                config,
+                output,
+                file_path,
+                &content,
+                &found,
+                (line_number, end_line),
+            );
+        }
+        totals.matches += 1;
+    }
+    Ok(())
+}
+
+/*
+Breif Explanation: Prints every line a --multiline match covers, after the line the match starts on.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds search options.    
+    output: &mut Output - where the formatted lines are printed to.
+    file_path: &String - the file path for the associated file.
+    content: &str - the whole file that was searched.
+    found: &Match - the match within content.
+    lines: (usize, usize) - the line numbers the match starts and ends on.
+
+Returns: NA
+*/
+fn print_multiline_match(
+    config: &Config,
+    output: &mut Output,
+    file_path: &String,
+    content: &str,
+    found: &Match,
+    lines: (usize, usize),
+) {
+    let (start_line, end_line) = lines;
+    let line_start = content[..found.start()].rfind('\n').map_or(0, |i| i + 1);
+    let last_line = if found.as_str().ends_with('\n') {
+        found.end() - 1
+    } else {
+        found.end()
+    };
+    let line_end = content[last_line..]
+        .find('\n')
+        .map_or(content.len(), |i| last_line + i);
+    let match_end = found.end().min(line_end);
+    let text = if config.colored_output {
+        format!(
+            "{}{}{}",
+            &content[line_start..found.start()],
+            content[found.start()..match_end].red(),
+            &content[match_end..line_end]
+        )
+    } else {
+        content[line_start..line_end].to_string()
+    };
+    let mut output_list = Vec::new();
+    if config.show_filenames() {
+        output_list.push(display_path(config.path_display, file_path));
+    }
+    // --column gives editors a position to jump to, which for a match spanning lines includes the line it ends on
+    if config.column && end_line > start_line {
+        output_list.push(format!("{}-{}", start_line, end_line));
+    } else if config.print_line_numbers || config.column {
+        output_list.push(start_line.to_string());
+    }
+    if config.column {
+        let column = content[line_start..found.start()].chars().count() + 1;
+        output_list.push(column.to_string());
+    }
+    output_list.push(text);
+    output.emit(output_list.join(": "));
+}
+
+/*
+Breif Explanation: Makes raw bytes printable, escaping anything that is not printable ASCII as \xNN.
+
+Parameters: 
+    bytes: &[u8] - the bytes to be printed.
+
+Returns: 
+    String - the printable version of the bytes.
+*/
+fn escape_bytes(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &byte in bytes {
+        if byte == b' ' || byte.is_ascii_graphic() {
+            escaped.push(byte as char);
+        } else {
+            escaped.push_str(&format!("\\x{:02X}", byte));
+        }
+    }
+    escaped
+}
+
+/*
+Breif Explanation: Describes a matching line for JSON output.
+
+Parameters: 
+    re: &Regex - the regex pattern.
+    config: &Config - instance of a config struct that holds search options.    
+    line_number: usize - the associated line number in the file for the line.
+    line: &str - the line that was matched.
+
+Returns: 
+    JsonMatch - the line along with every match in it.
+*/
+fn json_match(re: &Regex, config: &Config, line_number: usize, line: &str) -> JsonMatch {
+    JsonMatch {
+        line_number,
+        line: line.to_string(),
+        submatches: find_matches(re, line, config)
+            .into_iter()
+            .map(|found| JsonSubmatch {
+                text: found.as_str().to_string(),
+                start: found.start(),
+                end: found.end(),
+            })
+            .collect(),
+    }
+}
+
+/*
+Breif Explanation: Serializes one event of the --json stream.
+
+Parameters: 
+    event: &JsonEvent - the event to be printed.
+
+Returns: 
+    String - the event as a JSON object on a single line.
+*/
+fn json_event_line(event: &JsonEvent) -> String {
+    serde_json::to_string(event).unwrap_or_default()
+}
+
+/*
+Breif Explanation: Prints each match in a line on its own instead of the whole line, used for -o.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds search options.    
+    output: &mut Output - where the matches are printed to.
+    re: &Regex - the regex pattern.
+    file_path: &String - the file path for the associated file.
+    line_number: usize - the associated line number in the file for the line.
+    line: &str - the line that was matched.
+
+Returns: NA
+*/
+fn print_only_matching(
+    config: &Config,
+    output: &mut Output,
+    re: &Regex,
+    file_path: &String,
+    line_number: usize,
+    line: &str,
+) {
+    // --max-per-line keeps lines with lots of matches from flooding the output
+    let max_per_line = config.max_per_line.unwrap_or(usize::MAX);
+    for found in find_matches(re, line, config)
+        .into_iter()
+        .take(max_per_line)
+    {
+        let matched = if config.json_escape {
+            visible_whitespace(config, &json_escaped(found.as_str()))
+        } else {
+            visible_whitespace(config, found.as_str())
+        };
+        let matched = if config.colored_output {
+            matched.red().to_string()
+        } else {
+            matched
+        };
+        print_match(config, output, file_path, line_number, &matched);
+    }
+}
+
+/*
+Breif Explanation: Splits a line read from a file into its content and its line terminator.
+
+Parameters: 
+    line_bytes: &[u8] - the line as read from the file including any "\n" or "\r\n" terminator.
+
+Returns: 
+   (content: &[u8], terminator: &[u8]):
+        content - the line without its terminator
+        terminator - the terminator itself, empty for a final line without a newline
+*/
+fn split_line_terminator(line_bytes: &[u8]) -> (&[u8], &[u8]) {
+    if line_bytes.ends_with(b"\r\n") {
+        line_bytes.split_at(line_bytes.len() - 2)
+    } else if line_bytes.ends_with(b"\n") {
+        line_bytes.split_at(line_bytes.len() - 1)
+    } else {
+        (line_bytes, &[])
+    }
+}
+
+/*
+Breif Explanation: Replaces every match of the pattern in a line.
+
+Parameters: 
+    re: &Regex - the regex pattern.
+    replacement: &str - the replacement text, $0 refers to the whole match.
+    replace_case: &[CaseSegment] - the replacement split up at its case operators, used instead of replacement when not empty.
+    line: &str - the line to be transformed.
+    config: &Config - instance of a config struct that holds the --word-chars option.
+
+Returns: 
+   (replaced_line: String, replacements: usize):
+        replaced_line - the line with every non-overlapping match replaced
+        replacements - the number of substitutions made in the line
+*/
+fn replace_in_line(
+    re: &Regex,
+    replacement: &str,
+    replace_case: &[CaseSegment],
+    line: &str,
+    config: &Config,
+) -> (String, usize) {
+    // like the count flag of sed's s command, --replace-max-per-line leaves the later matches on the line alone
+    let max_per_line = config.replace_max_per_line.unwrap_or(usize::MAX);
+    //matches rejected by --word-chars have to be skipped so only the accepted ones are expanded by hand
+    if config.word_chars.is_some() {
+        let mut replaced_line = String::new();
+        let mut last_end = 0;
+        let mut matches = find_matches(re, line, config);
+        matches.truncate(max_per_line);
+        for found in &matches {
+            if let Some(caps) = re.captures_at(line, found.start()) {
+                replaced_line.push_str(&line[last_end..found.start()]);
+                if replace_case.is_empty() {
+                    expand_template(&caps, replacement, config, &mut replaced_line);
+                } else {
+                    expand_with_case(&caps, replace_case, config, &mut replaced_line);
+                }
+                last_end = found.end();
+            }
+        }
+        replaced_line.push_str(&line[last_end..]);
+        return (replaced_line, matches.len());
+    }
+    let replacements = re.find_iter(line).take(max_per_line).count();
+    //a limit of 0 makes replacen replace every match
+    let limit = config.replace_max_per_line.unwrap_or(0);
+    let replaced_line = if replace_case.is_empty() && !config.transforms_matches() {
+        re.replacen(line, limit, replacement)
+    } else {
+        re.replacen(line, limit, |caps: &Captures| {
+            let mut expanded = String::new();
+            if replace_case.is_empty() {
+                expand_template(caps, replacement, config, &mut expanded);
+            } else {
+                expand_with_case(caps, replace_case, config, &mut expanded);
+            }
+            expanded
+        })
+    };
+    (replaced_line.to_string(), replacements)
+}
+
+/*
+Breif Explanation: Expands the group references of a replacement for one match, like Captures::expand does.
+
+Parameters: 
+    caps: &Captures - the capture groups of the match.
+    template: &str - the replacement, $0 refers to the whole match and $1 or ${name} to capture groups.
+    config: &Config - instance of a config struct that holds the --json-escape and --normalize options.
+    expanded: &mut String - where the expanded replacement is written to.
+
+Returns: NA
+*/
+fn expand_template(caps: &Captures, template: &str, config: &Config, expanded: &mut String) {
+    if !config.transforms_matches() {
+        caps.expand(template, expanded);
+        return;
+    }
+    // the references are read the same way as replacement_group_references so they mean what they do without escaping
+    let mut rest = template;
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        rest = &rest[dollar + 1..];
+        if let Some(after) = rest.strip_prefix('$') {
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+        // an unclosed brace is treated as literal text by the regex crate
+        let close = rest.strip_prefix('{').and_then(|braced| braced.find('}'));
+        let group = if let Some(close) = close {
+            let group = &rest[1..close + 1];
+            rest = &rest[close + 2..];
+            group
+        } else {
+            let name_length = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let group = &rest[..name_length];
+            rest = &rest[name_length..];
+            group
+        };
+        // a lone $ is kept as it is and groups that did not take part in the match are left empty
+        if group.is_empty() {
+            expanded.push('$');
+            continue;
+        }
+        let found = match group.parse::<usize>() {
+            Ok(index) => caps.get(index),
+            Err(_) => caps.name(group),
+        };
+        if let Some(found) = found {
+            expanded.push_str(&transformed_match(config, found.as_str()));
+        }
+    }
+    expanded.push_str(rest);
+}
+
+/*
+Breif Explanation: Changes the text of a match or capture group for the replacement, normalizing it for --normalize and then escaping it for --json-escape.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds the --json-escape and --normalize options.
+    text: &str - the matched text.
+
+Returns: 
+    String - the changed text, normalizing can make it longer or shorter in bytes, like é as one or two characters.
+*/
+fn transformed_match(config: &Config, text: &str) -> String {
+    let normalized: String = match config.normalize {
+        Some(NormalForm::Nfc) => text.nfc().collect(),
+        Some(NormalForm::Nfd) => text.nfd().collect(),
+        Some(NormalForm::Nfkc) => text.nfkc().collect(),
+        Some(NormalForm::Nfkd) => text.nfkd().collect(),
+        None => text.to_string(),
+    };
+    if config.json_escape {
+        json_escaped(&normalized)
+    } else {
+        normalized
+    }
+}
+
+/*
+Breif Explanation: Escapes text so it can be put inside a JSON string, for --json-escape.
+
+Parameters: 
+    text: &str - the matched text.
+
+Returns: 
+    String - the text with quotes, backslashes and control characters escaped, without the quotes around it.
+*/
+fn json_escaped(text: &str) -> String {
+    let quoted = serde_json::to_string(text).unwrap_or_default();
+    quoted
+        .strip_prefix('"')
+        .and_then(|quoted| quoted.strip_suffix('"'))
+        .unwrap_or("")
+        .to_string()
+}
+
+/*
+Breif Explanation: Expands the replacement for one match, changing the case of each part as its \U, \L or \E operator says.
+
+Parameters: 
+    caps: &Captures - the capture groups of the match.
+    segments: &[CaseSegment] - the replacement split up at its case operators, from case_segments.
+    config: &Config - instance of a config struct that holds the --json-escape and --normalize options.
+    expanded: &mut String - where the expanded replacement is written to.
+
+Returns: NA
+*/
+fn expand_with_case(
+    caps: &Captures,
+    segments: &[CaseSegment],
+    config: &Config,
+    expanded: &mut String,
+) {
+    for segment in segments {
+        let mut part = String::new();
+        expand_template(caps, &segment.template, config, &mut part);
+        match segment.case {
+            CaseChange::Upper => expanded.push_str(&part.to_uppercase()),
+            CaseChange::Lower => expanded.push_str(&part.to_lowercase()),
+            CaseChange::Keep => expanded.push_str(&part),
+        }
+    }
+}
+
+/*
+Breif Explanation: Splits a replacement at its sed style \U, \L and \E case operators, \U upper cases and \L lower cases
+everything after it, both the text and the groups it refers to, until the next operator and \E ends the change.
+
+Parameters: 
+    replacement: &str - the replacement text as given on the command line.
+
+Returns: 
+    Vec<CaseSegment> - each part of the replacement with its escapes translated and how it is cased,
+        empty when there are no case operators so the replacement is used as it is.
+*/
+fn case_segments(replacement: &str) -> Vec<CaseSegment> {
+    let mut segments = Vec::new();
+    let mut case = CaseChange::Keep;
+    let mut template = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            template.push(c);
+            continue;
+        }
+        let next_case = match chars.next() {
+            Some('U') => CaseChange::Upper,
+            Some('L') => CaseChange::Lower,
+            Some('E') => CaseChange::Keep,
+            // every other escape, \\ included, is left for unescape_replacement
+            Some(other) => {
+                template.push('\\');
+                template.push(other);
+                continue;
+            }
+            None => {
+                template.push('\\');
+                continue;
+            }
+        };
+        segments.push(CaseSegment {
+            case,
+            template: unescape_replacement(&template),
+        });
+        case = next_case;
+        template.clear();
+    }
+    if segments.is_empty() {
+        return segments;
+    }
+    segments.push(CaseSegment {
+        case,
+        template: unescape_replacement(&template),
+    });
+    segments
+}
+
+/*
+Breif Explanation: Gives a replaced line the indentation of the original line back for --keep-indent.
+
+Parameters: 
+    line: &str - the line before the replacement.
+    replaced_line: &str - the line after the replacement.
+
+Returns: 
+    String - the replaced line with its own leading whitespace swapped for the leading whitespace of the original line.
+*/
+fn keep_indent(line: &str, replaced_line: &str) -> String {
+    let indent_length = line.len() - line.trim_start_matches([' ', '\t']).len();
+    format!(
+        "{}{}",
+        &line[..indent_length],
+        replaced_line.trim_start_matches([' ', '\t'])
+    )
+}
+
+/*
+Breif Explanation: Applies every --sub rule to a line in the order they were given.
+
+Parameters: 
+    subs: &[Substitution] - the rules to be applied.
+    line: &str - the line to be transformed.
+
+Returns: 
+    (String, Vec<usize>) - the line after every rule was applied and how many substitutions each rule made.
+*/
+fn apply_substitutions(subs: &[Substitution], line: &str) -> (String, Vec<usize>) {
+    let mut substituted_line = line.to_string();
+    let mut rule_counts = Vec::new();
+    for sub in subs {
+        rule_counts.push(sub.find.find_iter(&substituted_line).count());
+        substituted_line = sub
+            .find
+            .replace_all(&substituted_line, sub.replacement.as_str())
+            .to_string();
+    }
+    (substituted_line, rule_counts)
+}
+
+/*
+Breif Explanation: Asks on stderr if a change should be applied with --confirm, showing the line before and after.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds the --confirm option.
+    file_path: &String - the file path for the associated file.
+    line_number: usize - the associated line number in the file for the line.
+    line: &str - the line as it is in the file.
+    changed_line: Option<&str> - the line after the change, None when the line would be deleted.
+
+Returns: 
+    true if the change should be applied and false otherwise.
+*/
+fn change_confirmed(
+    config: &Config,
+    file_path: &String,
+    line_number: usize,
+    line: &str,
+    changed_line: Option<&str>,
+) -> bool {
+    if !config.confirm {
+        return true;
+    }
+    let changed_line = match changed_line {
+        Some(changed_line) => format!("+ {}", changed_line),
+        None => String::from("(line deleted)"),
+    };
+    ask_yes_no(&format!(
+        "{}: {}\n- {}\n{}\napply this change?",
+        file_path, line_number, line, changed_line
+    ))
+}
+
+/*
+Brief Explanation: Asks a question on stderr and waits for a yes or no answer, used by --confirm and --confirm-summary.
+
+Parameters: 
+    prompt: &str - the question, with anything to show before it on the lines above.
+
+Returns: 
+    true if the answer was y or yes and false otherwise, without a terminal to answer from it is always false.
+*/
+fn ask_yes_no(prompt: &str) -> bool {
+    // without a terminal to answer from every question is turned down
+    if !io::stdin().is_terminal() {
+        return false;
+    }
+    eprint!("{} [y/n] ", prompt);
+    let mut answer = String::new();
+    match io::stdin().read_line(&mut answer) {
+        Ok(_) => matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"),
+        Err(_) => false,
+    }
+}
+
+/*
+Breif Explanation: Turns the \n, \t, \r and \\ escapes of a --replace value into the characters they stand for.
+
+Parameters: 
+    replacement: &str - the replacement text as given on the command line.
+
+Returns: 
+    String - the replacement text with escapes translated, any other backslash is kept as it is.
+*/
+fn unescape_replacement(replacement: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = replacement.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some('\\') => unescaped.push('\\'),
+            Some(other) => {
+                unescaped.push('\\');
+                unescaped.push(other);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/*
+Breif Explanation: Fills in the {file} and {line} tokens of a replacement for the line being replaced.
+
+Parameters: 
+    replacement: &str - the replacement text with its escapes already translated.
+    file_path: &str - the path of the file as it is displayed.
+    line_number: usize - the number of the line being replaced.
+
+Returns: 
+    String - the replacement with the tokens filled in, a $ in the path is kept as a literal $, \{ is a literal {
+        and group references like ${name} are left for the regex.
+*/
+fn fill_replacement_tokens(replacement: &str, file_path: &str, line_number: usize) -> String {
+    let mut filled = String::new();
+    let mut rest = replacement;
+    while let Some(c) = rest.chars().next() {
+        if let Some(after) = rest.strip_prefix("\\{") {
+            filled.push('{');
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("$$") {
+            filled.push_str("$$");
+            rest = after;
+        } else if rest.starts_with("${") {
+            let end = rest.find('}').map_or(rest.len(), |i| i + 1);
+            filled.push_str(&rest[..end]);
+            rest = &rest[end..];
+        } else if let Some(after) = rest.strip_prefix("{file}") {
+            filled.push_str(&file_path.replace('$', "$$"));
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{line}") {
+            filled.push_str(&line_number.to_string());
+            rest = after;
+        } else {
+            filled.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    filled
+}
+
+/*
+Breif Explanation: Builds the replacement that puts the --prepend and --append text around each match.
+
+Parameters: 
+    prefix: &str - the text inserted right before each match, as given on the command line.
+    suffix: &str - the text inserted right after each match, as given on the command line.
+
+Returns: 
+    String - the replacement $0 with the prefix and suffix around it, a $ in them is kept as a literal $.
+*/
+fn wrap_replacement(prefix: &str, suffix: &str) -> String {
+    format!(
+        "{}${{0}}{}",
+        unescape_replacement(prefix).replace('$', "$$"),
+        unescape_replacement(suffix).replace('$', "$$")
+    )
+}
+
+/*
+Breif Explanation: Splits a --sub rule on its first = that is not escaped as \= and compiles the find part.
+
+Parameters: 
+    rule: &str - the rule as given on the command line, <find>=<replace>.
+    case_insensitive: bool - the option set if the find regex should ignore case.
+
+Returns: 
+    Ok(Substitution) - the compiled rule.
+    Err(String) - Error mesage if the rule has no = or the find part is not a valid regex.
+*/
+fn parse_substitution(rule: &str, case_insensitive: bool) -> Result<Substitution, String> {
+    let mut find = String::new();
+    let mut replacement = None;
+    let mut chars = rule.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('=') => find.push('='),
+                // other escapes are regex syntax so they are kept as they were
+                Some(escaped) => {
+                    find.push('\\');
+                    find.push(escaped);
+                }
+                None => find.push('\\'),
+            },
+            '=' => {
+                replacement = Some(chars.as_str().to_string());
+                break;
+            }
+            _ => find.push(c),
+        }
+    }
+    let replacement = match replacement {
+        Some(replacement) => replacement,
+        None => return Err(format!("Error: --sub requires <find>=<replace>: {}", rule)),
+    };
+    match RegexBuilder::new(&find)
+        .case_insensitive(case_insensitive)
+        .build()
+    {
+        Ok(find) => Ok(Substitution { find, replacement }),
+        Err(_) => Err(format!("Error: invalid --sub regex: {}", find)),
+    }
+}
+
+/*
+Breif Explanation: Writes the new contents of a file to a temporary file next to it, with the same permissions.
+
+Parameters: 
+    file_path: &String - the file to be replaced.
+    content: &[u8] - the new contents of the file.
+
+Returns: 
+    Ok(PathBuf) - the path of the temporary file.
+    Err(String) - Error mesage if the new contents could not be written.
+*/
+fn write_temp_file(file_path: &String, content: &[u8]) -> Result<PathBuf, String> {
+    let path = Path::new(file_path);
+    let file_name = match path.file_name() {
+        Some(file_name) => file_name.to_string_lossy(),
+        None => return Err(format!("Could not write file: {}", file_path)),
+    };
+    let temp_path = path.with_file_name(format!(".{}.search_utility.tmp", file_name));
+    if fs::write(&temp_path, content).is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return Err(format!("Could not write file: {}", file_path));
+    }
+    // keep the permissions of the original file
+    if let Ok(metadata) = fs::metadata(path) {
+        let _ = fs::set_permissions(&temp_path, metadata.permissions());
+    }
+    Ok(temp_path)
+}
+
+/*
+Breif Explanation: Writes every edited file for --transaction, no file is replaced unless all of them could be written.
+
+Parameters: 
+    pending_writes: &[(String, Vec<u8>)] - the edited files and their new contents.
+
+Returns: 
+    Ok(()) - every file replaced.
+    Err(String) - Error mesage if a file could not be written, the temporary files are removed again.
+*/
+fn commit_transaction(pending_writes: &[(String, Vec<u8>)]) -> Result<(), String> {
+    let mut temp_paths = Vec::new();
+    for (file_path, content) in pending_writes {
+        match write_temp_file(file_path, content) {
+            Ok(temp_path) => temp_paths.push(temp_path),
+            Err(e) => {
+                for temp_path in &temp_paths {
+                    let _ = fs::remove_file(temp_path);
+                }
+                return Err(format!(
+                    "{}, the transaction was rolled back and no files were changed",
+                    e
+                ));
+            }
+        }
+    }
+    // renames within a directory do not fail for lack of space so this is where the files are swapped in
+    for (index, (temp_path, (file_path, _))) in temp_paths.iter().zip(pending_writes).enumerate() {
+        if fs::rename(temp_path, file_path).is_err() {
+            for temp_path in &temp_paths[index..] {
+                let _ = fs::remove_file(temp_path);
+            }
+            return Err(format!(
+                "Could not write file: {}, {} of {} files had already been changed",
+                file_path,
+                index,
+                pending_writes.len()
+            ));
+        }
+    }
+    Ok(())
+}
+
+/*
+Breif Explanation: Replaces the contents of a file without ever leaving it half written.
+
+Parameters: 
+    file_path: &String - the file to be replaced.
+    content: &[u8] - the new contents of the file.
+
+Returns: 
+    Ok(()) - file replaced.
+    Err(String) - Error mesage if the new contents could not be written.
+*/
+fn write_file_atomically(file_path: &String, content: &[u8]) -> Result<(), String> {
+    // the new contents go to a temporary file in the same directory which is then renamed over the original
+    let temp_path = write_temp_file(file_path, content)?;
+    if fs::rename(&temp_path, file_path).is_err() {
+        let _ = fs::remove_file(&temp_path);
+        return Err(format!("Could not write file: {}", file_path));
+    }
+    Ok(())
+}
+
+/*
+Breif Explanation: Searches for pattern in a given line.
+
+Parameters: 
+    re: &Regex - the regex pattern.
+    config: &Config - instance of a config struct that holds the coloring and matching options.
+    line: &String - the line to be searched.    
+
+Returns: 
+   (pattern_found: bool, display_line: &String):
+        pattern_found - holds if pattern was found
+        display_line - holds the line that was searched and if colored_output was selected then the matched pattern is replaced for red version.
+*/
+fn pattern_in_line(re: &Regex, config: &Config, line: &String) -> (bool, String) {
+    // no match found so return as is
+    if !line_matches(re, config, line) {
+        return (false, line.to_string());
+    }
+    //match found but not trying to color or show whitespace so return as is
+    if !config.colored_output && !config.show_whitespace {
+        return (true, line.to_string());
+    }
+    let highlight_line = config.highlight_line && config.colored_output;
+    //match found but color needed
+
+    //with --highlight-line every part of the line gets the background so it survives the resets after each match
+    //overlapping matches are merged into one colored region and --word-chars rejects some matches,
+    //--show-whitespace only changes the matched text so each match is rebuilt by hand
+    if highlight_line || config.overlapping || config.word_chars.is_some() || config.show_whitespace
+    {
+        let mut highlighted_line = String::new();
+        let mut last_end = 0;
+        for (start, end) in merge_spans(&find_matches(re, line, config)) {
+            if start > last_end {
+                let between = &line[last_end..start];
+                if highlight_line {
+                    highlighted_line.push_str(&between.black().on_yellow().to_string());
+                } else {
+                    highlighted_line.push_str(between);
+                }
+            }
+            let matched = visible_whitespace(config, &line[start..end]);
+            if !config.colored_output {
+                highlighted_line.push_str(&matched);
+            } else if highlight_line {
+                highlighted_line.push_str(&matched.red().bold().on_yellow().to_string());
+            } else {
+                highlighted_line.push_str(&matched.red().to_string());
+            }
+            last_end = end;
+        }
+        if last_end < line.len() {
+            if highlight_line {
+                highlighted_line.push_str(&line[last_end..].black().on_yellow().to_string());
+            } else {
+                highlighted_line.push_str(&line[last_end..]);
+            }
+        }
+        return (true, highlighted_line);
+    }
+    if config.color_groups && config.colored_output {
+        let colored_line = re.replace_all(line, |caps: &Captures| color_capture_groups(caps));
+        return (true, colored_line.to_string());
+    }
+    //caps[0] will hold exact matches from the line
+    //we use colorize crate to update color to red
+    let replacement = |caps: &Captures| caps[0].red().to_string();
+    //replace all non-overlapping matches in the line with the replacement
+    let colored_line = re.replace_all(line, &replacement);
+    (true, colored_line.to_string())
+}
+
+/*
+Breif Explanation: Colors a match for --color-groups, each capture group in its own color and the rest of the match red.
+
+Parameters: 
+    caps: &Captures - the capture groups of the match.
+
+Returns: 
+    String - the colored match, a nested group takes the color of the innermost group around it.
+*/
+fn color_capture_groups(caps: &Captures) -> String {
+    let whole = match caps.get(0) {
+        Some(whole) => whole,
+        None => return String::new(),
+    };
+    // the match is cut wherever a group starts or ends so every piece lies wholly inside or outside of each group
+    let mut cuts = BTreeSet::from([whole.start(), whole.end()]);
+    let groups: Vec<(usize, Match)> = caps
+        .iter()
+        .enumerate()
+        .skip(1)
+        .filter_map(|(index, group)| group.map(|group| (index, group)))
+        .collect();
+    for (_, group) in &groups {
+        cuts.insert(group.start());
+        cuts.insert(group.end());
+    }
+    let cuts: Vec<usize> = cuts.into_iter().collect();
+    let mut colored_match = String::new();
+    for piece in cuts.windows(2) {
+        let (start, end) = (piece[0], piece[1]);
+        let text = &whole.as_str()[start - whole.start()..end - whole.start()];
+        // groups nested inside another always have a higher number so the last one covering the piece is the innermost
+        let color = groups
+            .iter()
+            .rev()
+            .find(|(_, group)| group.start() <= start && end <= group.end())
+            .map(|(index, _)| GROUP_COLORS[(index - 1) % GROUP_COLORS.len()]);
+        match color {
+            Some(color) => colored_match.push_str(&text.color(color).bold().to_string()),
+            None => colored_match.push_str(&text.red().to_string()),
+        }
+    }
+    colored_match
+}
+
+/*
+Breif Explanation: Makes the spaces and tabs in matched text visible for --show-whitespace.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds the --show-whitespace option.
+    matched: &str - the matched text.
+
+Returns: 
+    String - the matched text with spaces shown as · and tabs as →, or as it is without --show-whitespace.
+*/
+fn visible_whitespace(config: &Config, matched: &str) -> String {
+    if config.show_whitespace {
+        matched.replace(' ', "·").replace('\t', "→")
+    } else {
+        matched.to_string()
+    }
+}
+
+/*
+Breif Explanation: Finds every match of the pattern in a line, optionally including overlapping matches.
+
+Parameters: 
+    re: &Regex - the regex pattern.
+    line: &'a str - the line to be searched.
+    config: &Config - instance of a config struct that holds the --overlapping and --word-chars options.
+
+Returns: 
+    Vec<Match<'a>> - the matches in the order they start in the line.
+*/
+fn find_matches<'a>(re: &Regex, line: &'a str, config: &Config) -> Vec<Match<'a>> {
+    if !config.overlapping && config.word_chars.is_none() {
+        return re.find_iter(line).collect();
+    }
+    //with --overlapping the search starts again one character after where each match started,
+    //so "aaa" has two overlapping matches of "aa"
+    //a match rejected by --word-chars is retried one character later the same way
+    let mut matches = Vec::new();
+    let mut start = 0;
+    while start <= line.len() {
+        let found = match re.find_at(line, start) {
+            Some(found) => found,
+            None => break,
+        };
+        let accepted = match &config.word_chars {
+            Some(word_chars) => is_whole_word(word_chars, line, &found),
+            None => true,
+        };
+        let next_char_start = match line[found.start()..].chars().next() {
+            Some(c) => found.start() + c.len_utf8(),
+            None => line.len() + 1,
+        };
+        start = if config.overlapping || !accepted || found.is_empty() {
+            next_char_start
+        } else {
+            found.end()
+        };
+        if accepted {
+            matches.push(found);
+        }
+    }
+    matches
+}
+
+/*
+Breif Explanation: Checks if a line contains the pattern, taking --word-chars into account.
+
+Parameters: 
+    re: &Regex - the regex pattern.
+    config: &Config - instance of a config struct that holds the --word-chars option.
+    line: &str - the line to be searched.
+
+Returns: 
+    true if the line contains a match and false otherwise.
+*/
+fn line_matches(re: &Regex, config: &Config, line: &str) -> bool {
+    if config.word_chars.is_some() {
+        !find_matches(re, line, config).is_empty()
+    } else {
+        re.is_match(line)
+    }
+}
+
+/*
+Breif Explanation: Checks that a match is not directly next to a word character, used for -w with --word-chars.
+
+Parameters: 
+    word_chars: &Regex - regex matching a single word character.
+    line: &str - the line the match was found in.
+    found: &Match - the match to be checked.
+
+Returns: 
+    true if the match is a whole word and false otherwise.
+*/
+fn is_whole_word(word_chars: &Regex, line: &str, found: &Match) -> bool {
+    let mut char_buffer = [0; 4];
+    let mut is_word_char = |c: char| word_chars.is_match(c.encode_utf8(&mut char_buffer));
+    let before_ok = match line[..found.start()].chars().next_back() {
+        Some(c) => !is_word_char(c),
+        None => true,
+    };
+    let after_ok = match line[found.end()..].chars().next() {
+        Some(c) => !is_word_char(c),
+        None => true,
+    };
+    before_ok && after_ok
+}
+
+/*
+Breif Explanation: Merges matches that overlap or touch into single regions for highlighting.
+
+Parameters: 
+    matches: &[Match] - the matches in the order they start in the line.
+
+Returns: 
+    Vec<(usize, usize)> - the start and end byte offsets of each merged region.
+*/
+fn merge_spans(matches: &[Match]) -> Vec<(usize, usize)> {
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    for found in matches {
+        match spans.last_mut() {
+            Some(last) if found.start() <= last.1 => last.1 = last.1.max(found.end()),
+            _ => spans.push((found.start(), found.end())),
+        }
+    }
+    spans
+}
+
+/*
+Breif Explanation: Determines if a print is required based on if there was a match and inverted option was selected.
+
+Parameters: 
+    invert_match: bool - the option set if invert_match is selected in search configuration.
+    pattern_found: bool - was the pattern found in the given line.
+
+Returns: 
+   true if line should be printed and false otherwise.
+*/
+fn should_print(invert_match: bool, pattern_found: bool) -> bool {
+    if invert_match {
+        !pattern_found
+    } else {
+        pattern_found
+    }
+}
+
+/*
+Breif Explanation: Prints the matching lines of two files side by side for --compare, the first file on the left.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds search options.
+    file_paths: [&String; 2] - the file shown on the left and the file shown on the right.
+    re: &Regex - the regex pattern.
+    output: &mut Output - where the rows are printed to.
+
+Returns: 
+    Ok(usize) - the number of matching lines in both files.
+    Err(String) - Error mesage if either file could not be read.
+*/
+fn print_comparison(
+    config: &Config,
+    file_paths: [&String; 2],
+    re: &Regex,
+    output: &mut Output,
+) -> Result<usize, String> {
+    let mut columns = Vec::new();
+    for file_path in file_paths {
+        let mut buf_reader = open_for_search(file_path, config)?;
+        let mut matched_lines = Vec::new();
+        let mut line_bytes = Vec::new();
+        let mut line_number = 0;
+        loop {
+            line_bytes.clear();
+            match buf_reader.read_until(b'\n', &mut line_bytes) {
+                Ok(0) => break,
+                Ok(_) => (),
+                Err(_) => {
+                    return Err(format!(
+                        "Could not read line {} from {}",
+                        line_number + 1,
+                        file_path
+                    ))
+                }
+            }
+            line_number += 1;
+            let line = String::from_utf8_lossy(split_line_terminator(&line_bytes).0).to_string();
+            if should_print(config.invert_match, line_matches(re, config, &line)) {
+                if config.print_line_numbers {
+                    matched_lines.push(format!("{}: {}", line_number, line));
+                } else {
+                    matched_lines.push(line);
+                }
+            }
+        }
+        columns.push(matched_lines);
+    }
+    // each file gets half of the terminal, less the separator between the two columns
+    let width = terminal_size::terminal_size().map_or(80, |(width, _)| width.0 as usize);
+    let column_width = width.saturating_sub(3) / 2;
+    let (left, right) = (&columns[0], &columns[1]);
+    let left_path = display_path(config.path_display, file_paths[0]);
+    let right_path = display_path(config.path_display, file_paths[1]);
+    output.emit(format!(
+        "{} | {}",
+        fit_column(&left_path, column_width),
+        right_path
+    ));
+    for row in 0..left.len().max(right.len()) {
+        let left_line = left.get(row).map_or("", |line| line.as_str());
+        let right_line = right.get(row).map_or("", |line| line.as_str());
+        output.emit(format!(
+            "{} | {}",
+            fit_column(left_line, column_width),
+            fit_column(right_line, column_width).trim_end()
+        ));
+    }
+    Ok(left.len() + right.len())
+}
+
+/*
+Breif Explanation: Fits a line into a --compare column, cutting it short or padding it with spaces.
+
+Parameters: 
+    line: &str - the line to be shown in the column.
+    width: usize - the width of the column in characters.
+
+Returns: 
+    String - exactly width characters, a line that had to be cut short ends with … and tabs become spaces.
+*/
+fn fit_column(line: &str, width: usize) -> String {
+    let line = line.replace('\t', " ");
+    let length = line.chars().count();
+    if length > width {
+        let mut cut: String = line.chars().take(width.saturating_sub(1)).collect();
+        cut.push('…');
+        cut
+    } else {
+        format!("{}{}", line, " ".repeat(width - length))
+    }
+}
+
+/*
+Breif Explanation: prints matched line and associated data.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds search options.    
+    output: &mut Output - where the formatted line is printed to.
+    file_path: &String - the file path for the associated file.
+    line_number: usize - the associated line number in the file for the line.
+    line: &String - the line to be printed.
+
+Returns: NA
+*/
+fn print_match(
+    config: &Config,
+    output: &mut Output,
+    file_path: &String,
+    line_number: usize,
+    line: &String,
+) {
+    let mut output_list = Vec::new();
+    if config.show_filenames() {
+        let shown_path = display_path(config.path_display, file_path);
+        if config.hyperlink && file_path != "-" {
+            output_list.push(hyperlink_filename(file_path, &shown_path, line_number));
+        } else {
+            output_list.push(shown_path);
+        }
+    }
+    if config.print_line_numbers {
+        output_list.push(line_number.to_string());
+    }
+    // a line kept whole by --keep-newline already brings the newline that ends the output line
+    let line = if config.keep_newline {
+        line.strip_suffix('\n').unwrap_or(line)
+    } else {
+        line
+    };
+    let field;
+    let line = match config.field {
+        Some(field_number) => {
+            field = line_field(line, config.delimiter, field_number);
+            field.as_str()
         }
-        // ASSUMPTION: pattern will not be empty and will be correctly be input ahead of file paths
-        config.pattern = non_options[0].clone();
-        config.file_paths.extend_from_slice(&non_options[1..]);
-        // go through and find all file pths if recursive_search is set
-        if config.recursive_search {
-            config.file_paths = match recursively_find_all_files(&config.file_paths) {
-                Ok(found_file_paths) => found_file_paths,
-                Err(e) => return Err(e),
+        None => line,
+    };
+    match config.wrap_width {
+        Some(width) => {
+            let mut prefix = output_list.join(": ");
+            if !prefix.is_empty() {
+                prefix.push_str(": ");
             }
+            let wrapped_line = wrap_line(line, visible_width(&prefix), width);
+            output.emit(format!("{}{}", prefix, wrapped_line));
+        }
+        None => {
+            output_list.push(line.to_string());
+            output.emit(output_list.join(": "));
         }
-        Ok(config)
     }
 }
 
 /*
-Breif Explanation: Finds all files in given directory.
+Breif Explanation: Splits a line into its text and its color escape sequences.
 
 Parameters: 
-    directories: &Vec<String> - directory of all file paths to search.
+    line: &str - the line, it may contain color escape sequences.
 
 Returns: 
-    Ok(Vec<String>) - all files to be searched for pattern.
-    Err(String) - Error mesage if recursion fails.
+    Vec<(&str, bool)> - the parts of the line in order, with true for the escape sequences.
 */
-fn recursively_find_all_files(directories: &Vec<String>) -> Result<Vec<String>, String> {
-    let mut file_paths = Vec::new();
-    for directory in directories {
-        // get the metadata to see if file is actually a folder or not
-        let metadata = match fs::metadata(directory) {
-            Ok(metadata) => metadata,
-            Err(_) => return Err(format!("Error: could not get metadata for: {}", directory)),
-        };
-        // if it is a file then push to the entire filepath to vector
-        if metadata.is_file() {
-            file_paths.push(directory.to_string());
-        // if it is actually a directory walk through directory and push all files that are not hidden to the vector
-        } else if metadata.is_dir() {
-            for entry in WalkDir::new(directory) {
-                match entry {
-                    Ok(entry) => {
-                        if entry.file_type().is_file() {
-                            let file_name = entry.file_name().to_str().unwrap_or("");
-                            if !file_name.starts_with(".") {
-                                file_paths.push(entry.path().display().to_string());
-                            }
-                        }
-                    }
-                    Err(_) => return Err(format!("Error: could not read directory {}", directory)),
-                }
+fn ansi_segments(line: &str) -> Vec<(&str, bool)> {
+    let mut segments = Vec::new();
+    let mut last_end = 0;
+    for escape in ANSI_ESCAPE.find_iter(line) {
+        segments.push((&line[last_end..escape.start()], false));
+        segments.push((escape.as_str(), true));
+        last_end = escape.end();
+    }
+    segments.push((&line[last_end..], false));
+    segments
+}
+
+/*
+Breif Explanation: Gives one delimited field of a line for --field.
+
+Parameters: 
+    line: &str - the line to take the field from, it may contain color escape sequences.
+    delimiter: char - the character the fields are separated by.
+    field_number: usize - the field to keep, counted from 1.
+
+Returns: 
+    String - the text of the field, empty when the line has fewer fields.
+*/
+fn line_field(line: &str, delimiter: char, field_number: usize) -> String {
+    let mut field = String::new();
+    let mut current = 1;
+    for (segment, is_escape) in ansi_segments(line) {
+        // every escape sequence is kept so colors started before the field still apply and are turned off after it
+        if is_escape {
+            field.push_str(segment);
+            continue;
+        }
+        for c in segment.chars() {
+            if c == delimiter {
+                current += 1;
+            } else if current == field_number {
+                field.push(c);
             }
         }
     }
-    Ok(file_paths)
+    field
 }
 
-fn main() {
-    // get the the command line arguments and use them to intialize an instance of Config struct
-    let passed_args: Vec<String> = env::args().collect();
-    let config_set = match Config::new(&passed_args) {
-        Ok(config) => config,
-        Err(e) => {
-            println!("{e}");
-            display_help();
-            return;
+/*
+Breif Explanation: Soft wraps a line for --wrap, indenting continuation lines past the filename and line number prefix.
+
+Parameters: 
+    line: &str - the line to be wrapped, it may contain color escape sequences.
+    indent: usize - the width of the prefix printed before the line.
+    width: usize - the width of the terminal.
+
+Returns: 
+    String - the line with a newline and indentation added every time it reaches the terminal width.
+*/
+fn wrap_line(line: &str, indent: usize, width: usize) -> String {
+    //a prefix wider than the terminal still leaves one character per row
+    let available = width.saturating_sub(indent).max(1);
+    let mut wrapped_line = String::new();
+    // the colors in effect, they are turned off at the end of a row and turned on again on the next one
+    let mut active_colors = String::new();
+    let mut column = 0;
+    // only the text takes up columns, the escape sequences do not
+    for (segment, is_escape) in ansi_segments(line) {
+        if is_escape {
+            if segment == "\x1b[0m" {
+                active_colors.clear();
+            } else {
+                active_colors.push_str(segment);
+            }
+            wrapped_line.push_str(segment);
+            continue;
         }
-    };
-    // if the user entered a help option flag print the help message and exit
-    if config_set.help {
-        display_help();
-        return;
-    }
-    // go through all file paths and search through the file to find matches
-    for file_path in &config_set.file_paths {
-        match search_file(&file_path, &config_set) {
-            Ok(_) => (),
-            Err(e) => {
-                println!("{e}");
-                return;
+        for c in segment.chars() {
+            if column == available {
+                if !active_colors.is_empty() {
+                    wrapped_line.push_str("\x1b[0m");
+                }
+                wrapped_line.push('\n');
+                wrapped_line.push_str(&" ".repeat(indent));
+                wrapped_line.push_str(&active_colors);
+                column = 0;
             }
+            wrapped_line.push(c);
+            column += 1;
         }
     }
+    wrapped_line
 }
 
-fn display_help() {
-    println!(
-        "Usage: grep [OPTIONS] <pattern> <files...>
+/*
+Breif Explanation: Counts the characters of a string that actually take up space on the terminal.
 
-Options:
--i                Case-insensitive search
--n                Print line numbers
--v                Invert match (exclude lines that match the pattern)
--r                Recursive directory search
--f                Print filenames
--c                Enable colored output
--h, --help        Show help information"
-    );
+Parameters: 
+    text: &str - the text to be measured, it may contain color and hyperlink escape sequences.
+
+Returns: 
+    usize - the number of characters outside of escape sequences.
+*/
+fn visible_width(text: &str) -> usize {
+    ANSI_ESCAPE.replace_all(text, "").chars().count()
 }
 
 /*
-Breif Explanation: Searches for pattern in a given file.
+Breif Explanation: Prints a -A, -B or -C context line, dimmed with colored output.
 
 Parameters: 
-    file_path: &String - the file path for a given file.
     config: &Config - instance of a config struct that holds search options.    
+    output: &mut Output - where the line is printed to.
+    file_path: &String - the file path for the associated file.
+    line_number: usize - the associated line number in the file for the line.
+    line: &str - the context line.
+    last_printed: &mut Option<usize> - the line number of the last line printed from this file.
 
-Returns: 
-    Ok(()) - search done.
-    Err(String) - Error mesage if searching a file fails.
+Returns: NA
 */
-fn search_file(file_path: &String, config: &Config) -> Result<(), String> {
-    let f = match File::open(file_path) {
-        Ok(file) => file,
-        Err(_) => return Err(format!("Could not open file: {}", file_path)),
+fn print_context_line(
+    config: &Config,
+    output: &mut Output,
+    file_path: &String,
+    line_number: usize,
+    line: &str,
+    last_printed: &mut Option<usize>,
+) {
+    start_context_group(output, *last_printed, line_number);
+    *last_printed = Some(line_number);
+    let shown_line = if config.colored_output {
+        line.dimmed().to_string()
+    } else {
+        line.to_string()
     };
-    //buffer used to read a single line from the file at a time
-    let buf_reader = BufReader::new(f);
-    //regex created to try to find matches within a line
-    //escapes all regular expression meta characters in pattern
-    //case insensitity passed from config struct
-    //unicode enabled to esnure valid UTF-8 matches
-    let re = match RegexBuilder::new(&regex::escape(&config.pattern))
-        .case_insensitive(config.case_insensitive)
-        .unicode(true)
-        .build()
-    {
-        Ok(re) => re,
-        Err(_) => return Err(format!("Could not create regex builder for pattern")),
+    print_match(config, output, file_path, line_number, &shown_line);
+}
+
+/*
+Breif Explanation: Prints the line a fixed number of lines away from a match for --companion-offset, dimmed with colored output.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds search options.    
+    output: &mut Output - where the line is printed to.
+    file_path: &String - the file path for the associated file.
+    line_number: usize - the associated line number in the file for the line.
+    line: &str - the companion line.
+
+Returns: NA
+*/
+fn print_companion_line(
+    config: &Config,
+    output: &mut Output,
+    file_path: &String,
+    line_number: usize,
+    line: &str,
+) {
+    let shown_line = if config.colored_output {
+        line.dimmed().to_string()
+    } else {
+        line.to_string()
     };
-    //go line by line and if no error is hit then prin the line and the associated data based on the config instance
-    for (i, line_result) in buf_reader.lines().enumerate() {
-        match line_result {
-            Ok(line) => {
-                let (pattern_found, display_line) =
-                    pattern_in_line(&re, config.colored_output, &line);
-                if should_print(config.invert_match, pattern_found) {
-                    print_match(&config, &file_path, i + 1, &display_line)
-                }
+    print_match(config, output, file_path, line_number, &shown_line);
+}
+
+/*
+Breif Explanation: Prints the -- separator like grep does when a line is not directly after the last printed one.
+
+Parameters: 
+    output: &mut Output - where the separator is printed to.
+    last_printed: Option<usize> - the line number of the last line printed from this file.
+    line_number: usize - the line number about to be printed.
+
+Returns: NA
+*/
+fn start_context_group(output: &mut Output, last_printed: Option<usize>, line_number: usize) {
+    let gap = match last_printed {
+        Some(last_printed) => line_number > last_printed + 1,
+        // the first block of a file is separated from the blocks of earlier files
+        None => output.context_printed,
+    };
+    if gap {
+        output.emit(String::from("--"));
+    }
+    output.context_printed = true;
+}
+
+/*
+Breif Explanation: Prints how many lines matched in a file for --count, files without matches are only printed with --with-zero and those below --min-count never are.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds search options.    
+    output: &mut Output - where the count is printed to.
+    file_path: &String - the file path for the associated file.
+    count: usize - the number of matching lines in the file.
+
+Returns: 
+    None
+*/
+fn print_count(config: &Config, output: &mut Output, file_path: &String, count: usize) {
+    if !config.count_shown(count) {
+        return;
+    }
+    let shown_path = display_path(config.path_display, file_path);
+    match (config.count_format, config.show_filenames()) {
+        (CountFormat::Plain, true) => output.emit(format!("{}: {}", shown_path, count)),
+        (CountFormat::Plain, false) => output.emit(count.to_string()),
+        (_, show_filenames) => output.emit(formatted_count(
+            config.count_format,
+            Some(shown_path.as_str()).filter(|_| show_filenames),
+            count,
+        )),
+    }
+}
+
+/*
+Breif Explanation: Formats the number of matches in a file as key=value pairs or a JSON object for --count-format.
+
+Parameters: 
+    count_format: CountFormat - the style the count is printed in.
+    path: Option<&str> - the file the count is for, None when filenames are not printed.
+    count: usize - the number of matches.
+
+Returns: 
+    String - path=<path> count=<n> for kv, with the path quoted when it holds spaces, quotes or an =,
+        or {"path":...,"count":n} for json.
+*/
+fn formatted_count(count_format: CountFormat, path: Option<&str>, count: usize) -> String {
+    match count_format {
+        CountFormat::Json => serde_json::to_string(&JsonCount { path, count }).unwrap_or_default(),
+        _ => match path {
+            Some(path) => {
+                // the quoting is the same as a JSON string so any path can be read back
+                let needs_quotes = path.is_empty()
+                    || path
+                        .chars()
+                        .any(|c| c.is_whitespace() || c.is_control() || matches!(c, '"' | '='));
+                let shown_path = if needs_quotes {
+                    serde_json::to_string(path).unwrap_or_default()
+                } else {
+                    path.to_string()
+                };
+                format!("path={} count={}", shown_path, count)
             }
-            Err(_) => return Err(format!("Could not read line {} from {}", i + 1, file_path)),
+            None => format!("count={}", count),
+        },
+    }
+}
+
+/*
+Breif Explanation: Prints the files ranked by how many lines matched in them for --rank, ties are sorted by path.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds search options.    
+    output: &mut Output - where the ranking is printed to.
+    file_counts: &mut [(String, usize)] - the file paths searched and how many lines matched in each.
+
+Returns: NA
+*/
+fn print_ranking(config: &Config, output: &mut Output, file_counts: &mut [(String, usize)]) {
+    file_counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    for (file_path, count) in file_counts.iter() {
+        if !config.count_shown(*count) {
+            continue;
+        }
+        let shown_path = display_path(config.path_display, file_path);
+        match config.count_format {
+            CountFormat::Plain => output.emit(format!("{}: {}", count, shown_path)),
+            count_format => output.emit(formatted_count(count_format, Some(&shown_path), *count)),
         }
     }
-    Ok(())
 }
 
 /*
-Breif Explanation: Searches for pattern in a given line.
+Breif Explanation: Adds the value of a capture group in every match on a line to the --group-by tally.
 
 Parameters: 
     re: &Regex - the regex pattern.
-    colored_output: bool - the option set if colored output is selected in search configuration.
-    line: &String - the line to be searched.    
+    config: &Config - instance of a config struct that holds the --word-chars option.
+    group: usize - the capture group whose values are counted.
+    line: &str - the matching line.
+    group_counts: &mut HashMap<String, usize> - how often each value has been seen so far in the run.
 
-Returns: 
-   (pattern_found: bool, display_line: &String):
-        pattern_found - holds if pattern was found
-        display_line - holds the line that was searched and if colored_output was selected then the matched pattern is replaced for red version.
+Returns: NA
 */
-fn pattern_in_line(re: &Regex, colored_output: bool, line: &String) -> (bool, String) {
-    // no match found so return as is
-    if !re.is_match(line) {
-        return (false, line.to_string());
+fn count_group_values(
+    re: &Regex,
+    config: &Config,
+    group: usize,
+    line: &str,
+    group_counts: &mut HashMap<String, usize>,
+) {
+    for found in find_matches(re, line, config) {
+        // an optional group that did not take part in the match has no value to count
+        if let Some(value) = re
+            .captures_at(line, found.start())
+            .and_then(|caps| caps.get(group))
+        {
+            *group_counts.entry(value.as_str().to_string()).or_default() += 1;
+        }
     }
-    //match found but not trying to color so return as is
-    if !colored_output {
-        return (true, line.to_string());
+}
+
+/*
+Breif Explanation: Prints each value seen by --group-by with how often it was seen, most often first and ties sorted by value.
+
+Parameters: 
+    output: &mut Output - where the tally is printed to.
+    group_counts: &HashMap<String, usize> - how often each value was seen in the run.
+
+Returns: NA
+*/
+fn print_group_counts(output: &mut Output, group_counts: &HashMap<String, usize>) {
+    let mut values: Vec<(&String, &usize)> = group_counts.iter().collect();
+    values.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    for (value, count) in values {
+        output.emit(format!("{}: {}", value, count));
     }
-    //match found but color needed
+}
 
-    //caps[0] will hold exact matches from the line
-    //we use colorize crate to update color to red
-    let replacement = |caps: &Captures| caps[0].red().to_string();
-    //replace all non-overlapping matches in the line with the replacement
-    let colored_line = re.replace_all(line, &replacement);
-    (true, colored_line.to_string())
+/*
+Breif Explanation: Adds a matching line to the --time-group bucket its timestamp falls in.
+
+Parameters: 
+    config: &Config - instance of a config struct that holds the --time-regex option.
+    time_unit: TimeUnit - how long each bucket is.
+    line: &str - the matching line.
+    time_buckets: &mut BTreeMap<String, usize> - how many matching lines each bucket has so far in the run.
+
+Returns: NA
+*/
+fn count_time_bucket(
+    config: &Config,
+    time_unit: TimeUnit,
+    line: &str,
+    time_buckets: &mut BTreeMap<String, usize>,
+) {
+    // the first group of --time-regex, or the whole match when it has none, holds the timestamp
+    let timestamp = match &config.time_regex {
+        Some(time_regex) => time_regex
+            .captures(line)
+            .and_then(|caps| caps.get(1).or_else(|| caps.get(0)))
+            .map(|found| found.as_str()),
+        None => Some(line),
+    };
+    // lines without a timestamp of a form that can be bucketed are left out of the histogram
+    let caps = match timestamp.and_then(|timestamp| TIMESTAMP.captures(timestamp)) {
+        Some(caps) => caps,
+        None => return,
+    };
+    let date = format!("{}-{}-{}", &caps[1], &caps[2], &caps[3]);
+    let bucket = match (time_unit, caps.get(4), caps.get(5)) {
+        (TimeUnit::Day, _, _) => date,
+        (TimeUnit::Hour, Some(hour), _) => format!("{} {}:00", date, hour.as_str()),
+        (TimeUnit::Minute, Some(hour), Some(minute)) => {
+            format!("{} {}:{}", date, hour.as_str(), minute.as_str())
+        }
+        _ => return,
+    };
+    *time_buckets.entry(bucket).or_default() += 1;
 }
 
 /*
-Breif Explanation: Determines if a print is required based on if there was a match and inverted option was selected.
+Breif Explanation: Prints each --time-group bucket in time order with its number of matching lines and a bar to compare them by.
 
 Parameters: 
-    invert_match: bool - the option set if invert_match is selected in search configuration.
-    pattern_found: bool - was the pattern found in the given line.
+    output: &mut Output - where the histogram is printed to.
+    time_buckets: &BTreeMap<String, usize> - how many matching lines each bucket had in the run.
+
+Returns: NA
+*/
+fn print_time_histogram(output: &mut Output, time_buckets: &BTreeMap<String, usize>) {
+    // the bucket names are zero padded so sorting them as text also sorts them in time
+    let most = time_buckets.values().copied().max().unwrap_or(0);
+    let count_width = most.to_string().len();
+    for (bucket, count) in time_buckets {
+        let bar_length = (count * 40 / most).max(1);
+        output.emit(format!(
+            "{}: {:>width$} {}",
+            bucket,
+            count,
+            "#".repeat(bar_length),
+            width = count_width
+        ));
+    }
+}
+
+/*
+Breif Explanation: Wraps a filename in an OSC 8 terminal hyperlink pointing at the matched line.
+
+Parameters: 
+    file_path: &String - the file path for the associated file.
+    shown_path: &str - the file path as it should be displayed.
+    line_number: usize - the associated line number in the file for the line.
 
 Returns: 
-   true if line should be printed and false otherwise.
+    String - the filename wrapped in the hyperlink escape sequence.
 */
-fn should_print(invert_match: bool, pattern_found: bool) -> bool {
-    if invert_match {
-        !pattern_found
-    } else {
-        pattern_found
+fn hyperlink_filename(file_path: &String, shown_path: &str, line_number: usize) -> String {
+    // fall back to the path as given if it can not be resolved
+    let absolute_path = match fs::canonicalize(file_path) {
+        Ok(absolute_path) => absolute_path.display().to_string(),
+        Err(_) => file_path.to_string(),
+    };
+    format!(
+        "\x1b]8;;file://{}#{}\x1b\\{}\x1b]8;;\x1b\\",
+        absolute_path, line_number, shown_path
+    )
+}
+
+/*
+Breif Explanation: Normalizes a file path for display according to --path-display.
+
+Parameters: 
+    path_display: Option<PathDisplay> - how paths should be displayed, None leaves them as they were found.
+    file_path: &String - the file path for the associated file.
+
+Returns: 
+    String - the path to display, stdin is always shown as (standard input).
+*/
+fn display_path(path_display: Option<PathDisplay>, file_path: &String) -> String {
+    if file_path == "-" {
+        return String::from("(standard input)");
+    }
+    match path_display {
+        None => file_path.to_string(),
+        // fall back to the path as found whenever it can not be resolved
+        Some(PathDisplay::Absolute) => match fs::canonicalize(file_path) {
+            Ok(absolute_path) => absolute_path.display().to_string(),
+            Err(_) => file_path.to_string(),
+        },
+        // absolute paths below the current directory are made relative to it and any leading ./ is dropped
+        Some(PathDisplay::Relative) => {
+            let path = Path::new(file_path);
+            let relative_path = match env::current_dir() {
+                Ok(current_dir) if path.is_absolute() => {
+                    path.strip_prefix(current_dir).unwrap_or(path)
+                }
+                _ => path,
+            };
+            let relative_path = relative_path.strip_prefix(".").unwrap_or(relative_path);
+            relative_path.display().to_string()
+        }
     }
 }
 
 /*
-Breif Explanation: prints matched line and associated data.
+Breif Explanation: Prints a CSV row for every match in a line, or a single row without a match for inverted matches.
 
 Parameters: 
     config: &Config - instance of a config struct that holds search options.    
+    output: &mut Output - where the rows are printed to.
+    re: &Regex - the regex pattern.
     file_path: &String - the file path for the associated file.
     line_number: usize - the associated line number in the file for the line.
-    line: &String - the line to be printed.
+    line: &str - the line that was matched.
 
-Returns: NA
+Returns: 
+    Ok(()) - rows printed.
+    Err(String) - Error mesage if a row could not be written.
 */
-fn print_match(config: &Config, file_path: &String, line_number: usize, line: &String) {
-    let mut output_list = Vec::new();
-    if config.print_filenames {
-        output_list.push(file_path.to_string());
+fn print_csv_rows(
+    config: &Config,
+    output: &mut Output,
+    re: &Regex,
+    file_path: &String,
+    line_number: usize,
+    line: &str,
+) -> Result<(), String> {
+    let matches: Vec<(String, String)> = find_matches(re, line, config)
+        .into_iter()
+        .map(|found| ((found.start() + 1).to_string(), found.as_str().to_string()))
+        .collect();
+    let rows = if matches.is_empty() {
+        vec![(String::new(), String::new())]
+    } else {
+        matches
+    };
+    //the csv writer takes care of quoting fields holding commas, quotes or newlines, one writer is used for every row
+    let mut writer = csv::WriterBuilder::new()
+        .terminator(csv::Terminator::Any(b'\n'))
+        .from_writer(Vec::new());
+    let shown_path = display_path(config.path_display, file_path);
+    let line_number = line_number.to_string();
+    // a quoted field can hold a newline, so the rows are told apart by where each one ends
+    let mut row_ends = Vec::new();
+    for (column, matched) in rows {
+        let record = [shown_path.as_str(), &line_number, &column, &matched, line];
+        if writer.write_record(record).is_err() || writer.flush().is_err() {
+            return Err(format!("Could not write CSV row for {}", file_path));
+        }
+        row_ends.push(writer.get_ref().len());
     }
-    if config.print_line_numbers {
-        output_list.push(line_number.to_string());
+    let written = match writer.into_inner() {
+        Ok(written) => written,
+        Err(_) => return Err(format!("Could not write CSV row for {}", file_path)),
+    };
+    let mut row_start = 0;
+    for row_end in row_ends {
+        let row = String::from_utf8_lossy(&written[row_start..row_end]);
+        output.emit(row.trim_end_matches('\n').to_string());
+        row_start = row_end;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    thread_local! {
+        // how many regexes build_regex compiled on the thread running a test
+        pub static REGEX_BUILDS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    // the arguments are parsed the same way as on the command line, after the program name
+    fn parse_args(args: &[&str]) -> Config {
+        let args: Vec<String> = std::iter::once("search_utility")
+            .chain(args.iter().copied())
+            .map(String::from)
+            .collect();
+        Config::new(&args).unwrap()
+    }
+
+    // writes each file into a directory of its own for the test and gives back their paths
+    fn test_files(test: &str, contents: &[&str]) -> Vec<String> {
+        let directory = env::temp_dir().join(format!("search_utility_{}_{}", test, process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        contents
+            .iter()
+            .enumerate()
+            .map(|(index, content)| {
+                let file_path = directory.join(format!("{}.txt", index));
+                fs::write(&file_path, content).unwrap();
+                file_path.display().to_string()
+            })
+            .collect()
+    }
+
+    fn regex(config: &Config) -> Regex {
+        build_regex(config, &search_pattern(config)).unwrap()
+    }
+
+    // replaces the matches in one line the way a matching line is replaced when it is searched
+    fn replaced(config: &Config, line: &str) -> (String, usize) {
+        let re = regex(config);
+        let replacement = config.replace.as_ref().unwrap();
+        replace_in_line(&re, replacement, &config.replace_case, line, config)
+    }
+
+    #[test]
+    fn named_groups_are_expanded_in_the_replacement() {
+        let config = parse_args(&[
+            "-E",
+            "--replace",
+            "${month}/${year}",
+            r"(?P<year>\d{4})-(?P<month>\d{2})",
+            "a.txt",
+        ]);
+        assert!(validate_replacement_groups(&config, &regex(&config)).is_ok());
+        assert_eq!(
+            replaced(&config, "released 2024-05, fixed 2024-06"),
+            (String::from("released 05/2024, fixed 06/2024"), 2)
+        );
+    }
+
+    #[test]
+    fn unknown_named_group_is_rejected() {
+        let config = parse_args(&["-E", "--replace", "${day}", r"(?P<year>\d{4})", "a.txt"]);
+        let error = validate_replacement_groups(&config, &regex(&config)).unwrap_err();
+        assert!(error.contains("capture group day"), "{}", error);
+    }
+
+    #[test]
+    fn group_references_need_regex_mode() {
+        let config = parse_args(&["--replace", "$1", "foo", "a.txt"]);
+        let error = validate_replacement_groups(&config, &regex(&config)).unwrap_err();
+        assert!(error.contains("-E"), "{}", error);
+        let config = parse_args(&["--replace", "[$0]", "foo", "a.txt"]);
+        assert!(validate_replacement_groups(&config, &regex(&config)).is_ok());
+    }
+
+    #[test]
+    fn regex_is_built_once_for_every_file() {
+        let file_paths = test_files("regex_built_once", &["foo\n", "bar\nfoo\n", "foo foo\n"]);
+        let mut args = vec!["--silent-scan", "foo"];
+        args.extend(file_paths.iter().map(String::as_str));
+        let config = parse_args(&args);
+        let builds_before = REGEX_BUILDS.with(Cell::get);
+        let re = regex(&config);
+        let mut totals = RunTotals::new(&config);
+        let mut output = Output::new(&config);
+        let mut pending_writes = Vec::new();
+        for file_path in &config.file_paths {
+            search_file(
+                file_path,
+                &config,
+                &re,
+                &mut totals,
+                &mut output,
+                &mut pending_writes,
+            )
+            .unwrap();
+        }
+        assert_eq!(REGEX_BUILDS.with(Cell::get) - builds_before, 1);
+        assert_eq!(totals.matches, 3);
+    }
+
+    #[test]
+    fn line_terminator_is_split_off() {
+        assert_eq!(split_line_terminator(b"foo\n"), (&b"foo"[..], &b"\n"[..]));
+        assert_eq!(
+            split_line_terminator(b"foo\r\n"),
+            (&b"foo"[..], &b"\r\n"[..])
+        );
+        // the last line of a file without a final newline has no terminator to write back
+        assert_eq!(split_line_terminator(b"foo"), (&b"foo"[..], &b""[..]));
+        assert_eq!(split_line_terminator(b""), (&b""[..], &b""[..]));
+    }
+
+    #[test]
+    fn replacement_escapes_are_translated() {
+        assert_eq!(unescape_replacement(r"a\nb"), "a\nb");
+        assert_eq!(unescape_replacement(r"a\tb"), "a\tb");
+        assert_eq!(unescape_replacement(r"a\rb"), "a\rb");
+        assert_eq!(unescape_replacement(r"a\\nb"), "a\\nb");
+        // other escapes are left for the case operators and the regex, a trailing \ is kept
+        assert_eq!(unescape_replacement(r"\U$1\E"), r"\U$1\E");
+        assert_eq!(unescape_replacement(r"a\"), r"a\");
+    }
+
+    #[test]
+    fn replacement_escapes_work_with_group_references() {
+        let config = parse_args(&["-E", "--replace", r"$2\t$1\n", r"(\w+)=(\w+)", "a.txt"]);
+        assert_eq!(
+            replaced(&config, "key=value"),
+            (String::from("value\tkey\n"), 1)
+        );
+    }
+
+    #[test]
+    fn indentation_of_tabs_and_spaces_is_kept() {
+        // the match covered the indentation and took it away
+        assert_eq!(keep_indent("\t  foo()", "bar()"), "\t  bar()");
+        // the replacement brought indentation of its own, which is swapped for the original
+        assert_eq!(keep_indent("  \tfoo()", "\t\t  bar()"), "  \tbar()");
+        assert_eq!(keep_indent("foo()", "    bar()"), "bar()");
+    }
+
+    #[test]
+    fn keep_indent_applies_to_replaced_lines() {
+        let config = parse_args(&[
+            "-E",
+            "--replace",
+            "    bar",
+            "--keep-indent",
+            r"^\s*foo",
+            "a.txt",
+        ]);
+        let (replaced_line, replacements) = replaced(&config, " \t foo = 1");
+        assert_eq!(
+            (keep_indent(" \t foo = 1", &replaced_line), replacements),
+            (String::from(" \t bar = 1"), 1)
+        );
+    }
+
+    #[test]
+    fn replace_max_per_line_leaves_later_matches() {
+        let config = parse_args(&[
+            "--replace",
+            "X",
+            "--replace-max-per-line",
+            "2",
+            "a",
+            "a.txt",
+        ]);
+        assert_eq!(replaced(&config, "a-a-a-a"), (String::from("X-X-a-a"), 2));
+        assert_eq!(replaced(&config, "a-b"), (String::from("X-b"), 1));
+        let config = parse_args(&["--replace", "X", "a", "a.txt"]);
+        assert_eq!(replaced(&config, "a-a-a-a"), (String::from("X-X-X-X"), 4));
+    }
+
+    #[test]
+    fn replacement_is_split_at_case_operators() {
+        assert!(case_segments(r"$1-$2\n").is_empty());
+        let segments = case_segments(r"<\U$1\E-\L$2");
+        let parts: Vec<(&str, &str)> = segments
+            .iter()
+            .map(|segment| {
+                let case = match segment.case {
+                    CaseChange::Upper => "upper",
+                    CaseChange::Lower => "lower",
+                    CaseChange::Keep => "keep",
+                };
+                (case, segment.template.as_str())
+            })
+            .collect();
+        assert_eq!(
+            parts,
+            [
+                ("keep", "<"),
+                ("upper", "$1"),
+                ("keep", "-"),
+                ("lower", "$2")
+            ]
+        );
+    }
+
+    #[test]
+    fn each_case_operator_changes_the_replacement() {
+        let replace = |replacement: &str, line: &str| {
+            let config = parse_args(&["-E", "--replace", replacement, r"(\w+) (\w+)", "a.txt"]);
+            replaced(&config, line).0
+        };
+        assert_eq!(replace(r"\U$1 $2", "hello World"), "HELLO WORLD");
+        assert_eq!(replace(r"\L$1 $2", "HeLLo World"), "hello world");
+        assert_eq!(replace(r"\U$1\E $2", "hello World"), "HELLO World");
+        assert_eq!(replace(r"\U$1 \L$2", "hello WORLD"), "HELLO world");
+        // the text of the replacement is cased along with the groups
+        assert_eq!(replace(r"\Uid_$2", "get value"), "ID_VALUE");
+    }
+
+    #[test]
+    fn one_replacement_applies_to_every_pattern() {
+        let config = parse_args(&["-e", "cat", "-e", "dog", "--replace", "pet", "a.txt"]);
+        assert_eq!(
+            replaced(&config, "a cat, a dog and a bird"),
+            (String::from("a pet, a pet and a bird"), 2)
+        );
+        // with -E the groups are numbered across the patterns, a group of the pattern that did not match is empty
+        let config = parse_args(&[
+            "-E",
+            "-e",
+            "(cat)",
+            "-e",
+            "(dog)",
+            "--replace",
+            "<$1$2>",
+            "a.txt",
+        ]);
+        assert_eq!(
+            replaced(&config, "cat dog"),
+            (String::from("<cat> <dog>"), 2)
+        );
+    }
+
+    #[test]
+    fn matches_are_normalized_between_composed_and_decomposed() {
+        let normalize = |form: &str| {
+            parse_args(&[
+                "-E",
+                "--replace",
+                "$0",
+                "--normalize",
+                form,
+                r"\S+",
+                "a.txt",
+            ])
+        };
+        let composed = "caf\u{e9}";
+        let decomposed = "cafe\u{301}";
+        assert_eq!(transformed_match(&normalize("NFC"), decomposed), composed);
+        assert_eq!(transformed_match(&normalize("NFD"), composed), decomposed);
+        assert_eq!(transformed_match(&normalize("NFC"), composed), composed);
+        // the compatibility forms also split ligatures
+        assert_eq!(transformed_match(&normalize("NFKC"), "\u{fb01}ne"), "fine");
+        assert_eq!(transformed_match(&normalize("NFKD"), "\u{fb01}ne"), "fine");
+        // the replaced line can change length in bytes
+        let (replaced_line, replacements) = replaced(&normalize("NFC"), decomposed);
+        assert_eq!((replaced_line.as_str(), replacements), (composed, 1));
+        assert_eq!(decomposed.len() - replaced_line.len(), 1);
     }
-    output_list.push(line.to_string());
-    println!("{}", output_list.join(": "));
 }
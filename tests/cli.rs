@@ -0,0 +1,297 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+// an empty directory of its own for each test, the files of the test are written into it
+fn test_dir(test: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!(
+        "search_utility_cli_{}_{}",
+        test,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+// runs the program in directory with stdin piped in, giving back its exit code, stdout and stderr
+fn run(directory: &PathBuf, args: &[&str], stdin: &str) -> (i32, String, String) {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_search_utility"))
+        .args(args)
+        .current_dir(directory)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // the program can exit without reading its input, which closes the pipe before it is written
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    let output = child.wait_with_output().unwrap();
+    (
+        output.status.code().unwrap_or(-1),
+        String::from_utf8_lossy(&output.stdout).to_string(),
+        String::from_utf8_lossy(&output.stderr).to_string(),
+    )
+}
+
+#[test]
+fn in_place_edit_keeps_a_missing_final_newline() {
+    let directory = test_dir("missing_final_newline");
+    fs::write(directory.join("a.txt"), "foo\nbar foo").unwrap();
+    fs::write(directory.join("b.txt"), "foo\r\nbar foo\r\n").unwrap();
+    let (code, _, _) = run(
+        &directory,
+        &["--replace", "baz", "--in-place", "foo", "a.txt", "b.txt"],
+        "",
+    );
+    assert_eq!(code, 0);
+    assert_eq!(
+        fs::read_to_string(directory.join("a.txt")).unwrap(),
+        "baz\nbar baz"
+    );
+    assert_eq!(
+        fs::read_to_string(directory.join("b.txt")).unwrap(),
+        "baz\r\nbar baz\r\n"
+    );
+}
+
+#[test]
+fn context_of_adjacent_matches_is_merged_into_one_block() {
+    let directory = test_dir("adjacent_matches");
+    fs::write(
+        directory.join("a.txt"),
+        "a\nfoo\nb\nfoo\nc\nd\ne\nf\nfoo\ng\n",
+    )
+    .unwrap();
+    let expected = "1: a\n2: foo\n3: b\n4: foo\n5: c\n--\n8: f\n9: foo\n10: g\n";
+    for args in [
+        &["-n", "-C", "1", "foo", "a.txt"][..],
+        &["--bytes", "-n", "-C", "1", "foo", "a.txt"][..],
+    ] {
+        let (code, stdout, _) = run(&directory, args, "");
+        assert_eq!(code, 0);
+        assert_eq!(stdout, expected, "{:?}", args);
+    }
+}
+
+#[test]
+fn context_works_on_stdin() {
+    let directory = test_dir("stdin_context");
+    let (code, stdout, _) = run(
+        &directory,
+        &["-n", "-C", "1", "foo"],
+        "a\nfoo\nb\nc\nd\nfoo\n",
+    );
+    assert_eq!(code, 0);
+    assert_eq!(stdout, "1: a\n2: foo\n3: b\n--\n5: d\n6: foo\n");
+}
+
+#[test]
+fn stdin_is_searched_among_recursive_paths() {
+    let directory = test_dir("stdin_with_recursion");
+    fs::create_dir_all(directory.join("tree/sub")).unwrap();
+    fs::write(directory.join("a.txt"), "foo a\n").unwrap();
+    fs::write(directory.join("tree/sub/b.txt"), "foo b\n").unwrap();
+    let (code, stdout, stderr) = run(
+        &directory,
+        &["-r", "foo", "a.txt", "-", "tree"],
+        "foo stdin\n",
+    );
+    assert_eq!(code, 0, "{}", stderr);
+    assert_eq!(
+        stdout,
+        "a.txt: foo a\n(standard input): foo stdin\ntree/sub/b.txt: foo b\n"
+    );
+}
+
+#[test]
+fn context_of_two_files_is_separated_and_prefixed() {
+    let directory = test_dir("two_file_context");
+    fs::write(directory.join("a.txt"), "a\nfoo\nb\n").unwrap();
+    fs::write(directory.join("b.txt"), "c\nd\nfoo\n").unwrap();
+    let (code, stdout, _) = run(&directory, &["-n", "-C", "1", "foo", "a.txt", "b.txt"], "");
+    assert_eq!(code, 0);
+    // the context of b.txt never starts with the last lines of a.txt
+    assert_eq!(
+        stdout,
+        "a.txt: 1: a\na.txt: 2: foo\na.txt: 3: b\n--\nb.txt: 2: d\nb.txt: 3: foo\n"
+    );
+}
+
+#[test]
+fn multiline_replace_joins_two_lines() {
+    let directory = test_dir("multiline_replace");
+    fs::write(directory.join("a.txt"), "keep\nfirst\nsecond\nlast\n").unwrap();
+    let args = ["-U", "-E", "--replace", "both", r"first\nsecond", "a.txt"];
+    let (code, stdout, stderr) = run(&directory, &args, "");
+    assert_eq!(code, 0, "{}", stderr);
+    assert_eq!(stdout, "keep\nboth\nlast\n");
+    let (code, _, stderr) = run(&directory, &[&args[..], &["--in-place"]].concat(), "");
+    assert_eq!(code, 0, "{}", stderr);
+    assert_eq!(
+        fs::read_to_string(directory.join("a.txt")).unwrap(),
+        "keep\nboth\nlast\n"
+    );
+}
+
+#[test]
+fn recursive_output_of_each_file_stays_together() {
+    let directory = test_dir("recursive_file_order");
+    for index in 0..20 {
+        let subdirectory = directory.join(format!("tree/{}", index % 4));
+        fs::create_dir_all(&subdirectory).unwrap();
+        fs::write(
+            subdirectory.join(format!("{}.txt", index)),
+            "foo 1\nbar\nfoo 2\nfoo 3\n",
+        )
+        .unwrap();
+    }
+    let (code, stdout, stderr) = run(&directory, &["-r", "foo", "tree"], "");
+    assert_eq!(code, 0, "{}", stderr);
+    let paths: Vec<&str> = stdout
+        .lines()
+        .map(|line| line.split(": ").next().unwrap())
+        .collect();
+    assert_eq!(paths.len(), 60, "{}", stdout);
+    // the files can be found in any order but the three lines of each are printed one after the other
+    let mut files = HashSet::new();
+    for file_lines in paths.chunks(3) {
+        assert!(
+            file_lines.iter().all(|path| path == &file_lines[0]),
+            "{}",
+            stdout
+        );
+        assert!(files.insert(file_lines[0]), "{}", stdout);
+    }
+}
+
+#[test]
+fn recursive_output_held_back_counts_against_max_memory() {
+    let directory = test_dir("recursive_max_memory");
+    fs::create_dir_all(directory.join("tree")).unwrap();
+    fs::write(directory.join("tree/a.txt"), "foo\n".repeat(100)).unwrap();
+    let (code, _, stderr) = run(
+        &directory,
+        &["-r", "--max-memory", "100", "foo", "tree"],
+        "",
+    );
+    assert_eq!(code, 2);
+    assert!(stderr.contains("--max-memory"), "{}", stderr);
+    let (code, stdout, _) = run(
+        &directory,
+        &["-r", "--max-memory", "10K", "foo", "tree"],
+        "",
+    );
+    assert_eq!(code, 0);
+    assert_eq!(stdout.lines().count(), 100);
+}
+
+#[test]
+fn multiline_replace_rejects_limits_on_the_changes() {
+    let directory = test_dir("multiline_replace_limits");
+    fs::write(directory.join("a.txt"), "first\nsecond\nfirst\nsecond\n").unwrap();
+    for limit in [
+        &["-m", "1"][..],
+        &["--max-matches-total", "1"],
+        &["--preview", "1"],
+    ] {
+        let args = [
+            &["-U", "-E", "--replace", "both"][..],
+            limit,
+            &[r"first\nsecond", "a.txt"],
+        ]
+        .concat();
+        let (code, stdout, stderr) = run(&directory, &args, "");
+        assert_eq!(code, 2, "{:?}", args);
+        assert_eq!(stdout, "");
+        assert!(stderr.contains(limit[0]), "{}", stderr);
+    }
+}
+
+#[test]
+fn csv_path_column_follows_path_display() {
+    let directory = test_dir("csv_path_display");
+    fs::write(directory.join("a.txt"), "x foo, foo\n").unwrap();
+    let args = ["--csv", "--path-display=absolute", "foo", "a.txt", "-"];
+    let (code, stdout, stderr) = run(&directory, &args, "foo\n");
+    assert_eq!(code, 0, "{}", stderr);
+    let absolute_path = fs::canonicalize(directory.join("a.txt")).unwrap();
+    assert_eq!(
+        stdout,
+        format!(
+            "path,line_number,column,match,line\n{0},1,3,foo,\"x foo, foo\"\n{0},1,8,foo,\"x foo, foo\"\n(standard input),1,1,foo,foo\n",
+            absolute_path.display()
+        )
+    );
+}
+
+#[test]
+fn change_summaries_name_one_change_in_the_singular() {
+    let directory = test_dir("singular_change_summary");
+    fs::write(directory.join("a.txt"), "foo\nbar\n").unwrap();
+    fs::write(directory.join("b.txt"), "foo foo\n").unwrap();
+    let (_, _, stderr) = run(&directory, &["--replace", "baz", "foo", "a.txt"], "");
+    assert_eq!(stderr, "a.txt: 1 replacement\ntotal: 1 replacement\n");
+    let (_, _, stderr) = run(&directory, &["--delete-matches", "foo", "a.txt"], "");
+    assert_eq!(stderr, "a.txt: 1 line deleted\ntotal: 1 line deleted\n");
+    let (_, _, stderr) = run(
+        &directory,
+        &["--replace", "baz", "foo", "a.txt", "b.txt"],
+        "",
+    );
+    assert_eq!(
+        stderr,
+        "a.txt: 1 replacement\nb.txt: 2 replacements\ntotal: 3 replacements\n"
+    );
+}
+
+#[test]
+fn per_pattern_stats_count_lines_and_files_by_number() {
+    let directory = test_dir("per_pattern_stats");
+    fs::write(directory.join("a.txt"), "foo bar\nbar\n").unwrap();
+    fs::write(directory.join("b.txt"), "bar\n").unwrap();
+    let args = [
+        "-e",
+        "foo",
+        "-e",
+        "bar",
+        "--per-pattern-stats",
+        "a.txt",
+        "b.txt",
+    ];
+    let (code, stdout, _) = run(&directory, &args, "");
+    assert_eq!(code, 0);
+    assert!(
+        stdout.ends_with("\nfoo: 1 matched line in 1 file\nbar: 3 matched lines in 2 files\n"),
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn batch_summary_is_printed_and_confirm_summary_needs_a_terminal() {
+    let directory = test_dir("batch_summary");
+    fs::write(directory.join("a.txt"), "foo\n").unwrap();
+    let args = ["--replace", "bar", "--in-place", "foo", "a.txt"];
+    let (code, _, stderr) = run(
+        &directory,
+        &[&args[..], &["--confirm-summary"]].concat(),
+        "y\n",
+    );
+    // an answer piped in is never taken as agreeing to the changes
+    assert_eq!(code, 1);
+    assert!(stderr.contains("\n1 file, 1 replacement\n"), "{}", stderr);
+    assert_eq!(
+        fs::read_to_string(directory.join("a.txt")).unwrap(),
+        "foo\n"
+    );
+    let (code, _, stderr) = run(&directory, &args, "");
+    assert_eq!(code, 0);
+    assert!(stderr.contains("1 file, 1 replacement\n"), "{}", stderr);
+    assert_eq!(
+        fs::read_to_string(directory.join("a.txt")).unwrap(),
+        "bar\n"
+    );
+}